@@ -7,13 +7,18 @@ fn main() {
     // https://doc.rust-lang.org/nightly/cargo/reference/unstable.html#artifact-dependencies
     let kernel =
         PathBuf::from(std::env::var_os("CARGO_BIN_FILE_KERNEL").unwrap());
+    let roottask =
+        PathBuf::from(std::env::var_os("CARGO_BIN_FILE_ROOTTASK").unwrap());
 
-    // create an UEFI disk image (optional)
+    // create an UEFI disk image (optional), the root task is the ramdisk
     let uefi_path = out_dir.join("uefi.img");
     bootloader::UefiBoot::new(&kernel)
+        .set_ramdisk(&roottask)
         .create_disk_image(&uefi_path)
         .unwrap();
 
     // pass the disk image paths as env variables to the `main.rs`
     println!("cargo:rustc-env=UEFI_PATH={}", uefi_path.display());
+    // test kernels are packed by the runner with the same root task
+    println!("cargo:rustc-env=ROOTTASK_PATH={}", roottask.display());
 }
@@ -1,8 +1,13 @@
 use core::ptr::NonNull;
 
-use acpi::{AcpiHandler, PhysicalMapping};
+use acpi::madt::{Madt, MadtEntry};
+use acpi::{AcpiHandler, AcpiResult, AcpiTables, PhysicalMapping};
+use heapless::Vec;
+use spin::Once;
 use x86_64::{PhysAddr, VirtAddr};
 
+use crate::scheduler::MAX_CPUS;
+
 /// ACPI handler.
 #[derive(Debug, Clone, Copy)]
 pub struct Acpi {
@@ -45,3 +50,203 @@ impl AcpiHandler for Acpi {
 
 unsafe impl Send for Acpi {}
 unsafe impl Sync for Acpi {}
+
+/// Maximum number of IOAPICs.
+pub const MAX_IOAPICS: usize = 8;
+/// Maximum number of interrupt source overrides.
+pub const MAX_OVERRIDES: usize = 16;
+
+/// Local APIC entry flag, processor is usable.
+const LAPIC_ENABLED: u32 = 1 << 0;
+
+static MADT: Once<MadtInfo> = Once::new();
+
+/// IOAPIC description.
+#[derive(Debug, Clone, Copy)]
+pub struct IoApicInfo {
+    pub id: u8,
+    pub paddr: u64,
+    /// First GSI handled by this IOAPIC.
+    pub gsi_base: u32,
+}
+
+/// ISA interrupt routed to another GSI.
+#[derive(Debug, Clone, Copy)]
+pub struct SourceOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    /// MPS INTI flags, polarity in bits 0-1 and trigger mode in bits 2-3.
+    pub flags: u16,
+}
+
+impl SourceOverride {
+    pub fn active_low(&self) -> bool {
+        self.flags & 0b11 == 0b11
+    }
+
+    pub fn level_triggered(&self) -> bool {
+        (self.flags >> 2) & 0b11 == 0b11
+    }
+}
+
+/// Processor local APIC.
+#[derive(Debug, Clone, Copy)]
+pub struct Processor {
+    /// ACPI processor UID.
+    pub uid: u32,
+    pub apic_id: u32,
+    pub enabled: bool,
+}
+
+/// Interrupt controllers described by the MADT.
+#[derive(Debug, Clone)]
+pub struct MadtInfo {
+    pub lapic_paddr: u64,
+    pub ioapics: Vec<IoApicInfo, MAX_IOAPICS>,
+    pub overrides: Vec<SourceOverride, MAX_OVERRIDES>,
+    pub processors: Vec<Processor, MAX_CPUS>,
+}
+
+impl MadtInfo {
+    /// Read the MADT from the tables pointed by `rsdp_addr`.
+    ///
+    /// # Safety
+    /// `rsdp_addr` must be the RSDP physical address and physical memory
+    /// must be mapped at `physical_memory_offset`.
+    pub unsafe fn parse(
+        rsdp_addr: usize,
+        physical_memory_offset: VirtAddr,
+    ) -> AcpiResult<Self> {
+        let handler = Acpi::new(physical_memory_offset);
+        let tables = AcpiTables::from_rsdp(handler, rsdp_addr)?;
+        let mapping = tables.find_table::<Madt>()?;
+        let madt = mapping.get();
+
+        let mut info = Self {
+            lapic_paddr: madt.local_apic_address as u64,
+            ioapics: Vec::new(),
+            overrides: Vec::new(),
+            processors: Vec::new(),
+        };
+
+        for entry in madt.entries() {
+            match entry {
+                MadtEntry::LocalApic(lapic) => info.add_processor(Processor {
+                    uid: lapic.processor_id as u32,
+                    apic_id: lapic.apic_id as u32,
+                    enabled: lapic.flags & LAPIC_ENABLED != 0,
+                }),
+                MadtEntry::LocalX2Apic(x2apic) => {
+                    info.add_processor(Processor {
+                        uid: x2apic.processor_uid,
+                        apic_id: x2apic.x2apic_id,
+                        enabled: x2apic.flags & LAPIC_ENABLED != 0,
+                    })
+                },
+                MadtEntry::LocalApicAddressOverride(addr) => {
+                    info.lapic_paddr = addr.local_apic_address;
+                },
+                MadtEntry::IoApic(ioapic) => {
+                    let ioapic = IoApicInfo {
+                        id: ioapic.io_apic_id,
+                        paddr: ioapic.io_apic_address as u64,
+                        gsi_base: ioapic.global_system_interrupt_base,
+                    };
+                    if info.ioapics.push(ioapic).is_err() {
+                        log::warn!("too many IOAPICs, {ioapic:x?} ignored");
+                    }
+                },
+                MadtEntry::InterruptSourceOverride(iso) => {
+                    let iso = SourceOverride {
+                        irq: iso.irq,
+                        gsi: iso.global_system_interrupt,
+                        flags: iso.flags,
+                    };
+                    if info.overrides.push(iso).is_err() {
+                        log::warn!("too many overrides, {iso:?} ignored");
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        Ok(info)
+    }
+
+    fn add_processor(&mut self, processor: Processor) {
+        if self.processors.push(processor).is_err() {
+            log::warn!("too many processors, {processor:?} ignored");
+        }
+    }
+
+    /// Override of ISA `irq`, if any.
+    pub fn source_override(&self, irq: u8) -> Option<&SourceOverride> {
+        self.overrides.iter().find(|iso| iso.irq == irq)
+    }
+
+    /// GSI of ISA `irq`, identity mapped unless overridden.
+    pub fn isa_gsi(&self, irq: u8) -> u32 {
+        self.source_override(irq).map_or(irq as u32, |iso| iso.gsi)
+    }
+
+    /// Override targeting `gsi`, if any.
+    pub fn gsi_override(&self, gsi: u32) -> Option<&SourceOverride> {
+        self.overrides.iter().find(|iso| iso.gsi == gsi)
+    }
+}
+
+/// Parse and keep the MADT.
+///
+/// Must be called once, before [`madt`].
+pub fn init(
+    rsdp_addr: usize,
+    physical_memory_offset: VirtAddr,
+) -> &'static MadtInfo {
+    MADT.call_once(|| {
+        // SAFETY: the bootloader provides the RSDP and maps physical memory.
+        let madt =
+            unsafe { MadtInfo::parse(rsdp_addr, physical_memory_offset) }
+                .expect("failed to parse MADT");
+        log::info!(
+            "madt: {} processors, {} IOAPICs, {} overrides",
+            madt.processors.len(),
+            madt.ioapics.len(),
+            madt.overrides.len(),
+        );
+        madt
+    })
+}
+
+/// Parsed MADT.
+pub fn madt() -> &'static MadtInfo {
+    MADT.get().expect("MADT not parsed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn isa_irqs_follow_overrides() {
+        let mut info = MadtInfo {
+            lapic_paddr: 0xfee0_0000,
+            ioapics: Vec::new(),
+            overrides: Vec::new(),
+            processors: Vec::new(),
+        };
+        info.overrides
+            .push(SourceOverride {
+                irq: 0,
+                gsi: 2,
+                flags: 0b1111,
+            })
+            .unwrap();
+
+        assert_eq!(info.isa_gsi(0), 2);
+        assert_eq!(info.isa_gsi(1), 1);
+
+        let iso = info.gsi_override(2).unwrap();
+        assert!(iso.active_low() && iso.level_triggered());
+        assert!(info.gsi_override(0).is_none());
+    }
+}
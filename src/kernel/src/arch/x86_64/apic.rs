@@ -1,10 +1,35 @@
 use core::arch::x86_64::__cpuid;
 
+use crate::arch::acpi::{MAX_IOAPICS, MadtInfo};
 use crate::arch::constants::apic::*;
+use crate::arch::constants::interrupts::{IdtIndex, MAX_IRQS};
+use crate::arch::topology::topology;
 use crate::arch::{VirtAddr, pic};
+
+/// IOAPIC handling a contiguous range of GSIs.
+#[derive(Debug, Clone, Copy)]
+struct IoApic {
+    addr: VirtAddr,
+    gsi_base: u32,
+    entries: u32,
+}
+
+impl IoApic {
+    const NONE: Self = Self {
+        addr: VirtAddr::zero(),
+        gsi_base: 0,
+        entries: 0,
+    };
+
+    fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi - self.gsi_base < self.entries
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Apic {
-    io_apic_addr: VirtAddr,
+    ioapics: [IoApic; MAX_IOAPICS],
+    ioapic_count: usize,
     lapic_addr: VirtAddr,
 }
 
@@ -12,7 +37,8 @@ impl Apic {
     /// Create an [`Apic`] with no address.
     pub const fn new() -> Self {
         Self {
-            io_apic_addr: VirtAddr::zero(),
+            ioapics: [IoApic::NONE; MAX_IOAPICS],
+            ioapic_count: 0,
             lapic_addr: VirtAddr::zero(),
         }
     }
@@ -23,11 +49,6 @@ impl Apic {
         (cpuid_result.edx & apic_bit) != 0
     }
 
-    fn enable_io_apic(addr: VirtAddr) {
-        let ptr = addr.as_mut_ptr::<u32>();
-        unsafe { ptr.offset(0).write_volatile(0x12) };
-    }
-
     fn enable_lapic(addr: VirtAddr) {
         let ptr = addr.as_mut_ptr::<u32>();
         unsafe {
@@ -44,51 +65,146 @@ impl Apic {
         VirtAddr::new(paddr + vspace_offset)
     }
 
-    /// APIC initialization.
-    pub fn init(mut self, _rsdp_addr: usize, vspace_offset: u64) -> Self {
+    /// Enable current core LAPIC, on application processors.
+    pub fn init_ap(&self) {
+        Self::enable_lapic(self.lapic_addr);
+    }
+
+    /// APIC initialization from controllers described in `madt`.
+    pub fn init(mut self, madt: &MadtInfo, vspace_offset: u64) -> Self {
         if !Self::has_apic() {
             panic!("APIC is not supported");
         }
 
         pic::Pic::new().disable();
 
-        // TODO: Read RDSP without alloc.
-        let io_apic_addr = 0xFEC0_0000;
-        let lapic_addr = 0xFEE0_0000;
+        let lapic_addr = Self::map_apic(madt.lapic_paddr, vspace_offset);
+        Self::enable_lapic(lapic_addr);
+        self.lapic_addr = lapic_addr;
+
+        for (idx, ioapic) in madt.ioapics.iter().enumerate() {
+            self.ioapics[idx] = IoApic {
+                addr: Self::map_apic(ioapic.paddr, vspace_offset),
+                gsi_base: ioapic.gsi_base,
+                entries: 0,
+            };
+            self.ioapic_count = idx + 1;
 
-        let io_apic_addr = Self::map_apic(io_apic_addr, vspace_offset);
-        let lapic_addr = Self::map_apic(lapic_addr, vspace_offset);
+            let version =
+                self.ioapic_read(idx, ApicRegister::IoapicVersion as u32);
+            self.ioapics[idx].entries = ((version >> 16) & 0xff) + 1;
 
-        Self::enable_io_apic(io_apic_addr);
-        Self::enable_lapic(lapic_addr);
+            log::info!(
+                "ioapic {} at {:#x}, GSIs {}..{}",
+                ioapic.id,
+                ioapic.paddr,
+                ioapic.gsi_base,
+                ioapic.gsi_base + self.ioapics[idx].entries,
+            );
+        }
+
+        // Lines stay masked until the kernel or a handler claims them, then
+        // are served on the boot processor.
+        let dest = topology().bsp() << 24;
+        for idx in 0..self.ioapic_count {
+            let ioapic = self.ioapics[idx];
+            for gsi in ioapic.gsi_base..ioapic.gsi_base + ioapic.entries {
+                let mut value = ApicValue::RedirectionMasked as u32;
+                if (gsi as usize) < MAX_IRQS {
+                    value |= (IdtIndex::Irq as u8 + gsi as u8) as u32;
+                }
+                if let Some(iso) = madt.gsi_override(gsi) {
+                    if iso.active_low() {
+                        value |= ApicValue::RedirectionActiveLow as u32;
+                    }
+                    if iso.level_triggered() {
+                        value |= ApicValue::RedirectionLevel as u32;
+                    }
+                }
+
+                let low = Self::redirection_register(ioapic, gsi);
+                self.ioapic_write(idx, low + 1, dest);
+                self.ioapic_write(idx, low, value);
+            }
+        }
 
         log::info!(
-            "apic, lapic initialized at IOAPIC={:x} LAPIC={:x}",
-            io_apic_addr.as_u64(),
+            "apic, lapic initialized at LAPIC={:x}",
             lapic_addr.as_u64(),
         );
 
-        self.io_apic_addr = io_apic_addr;
-        self.lapic_addr = lapic_addr;
         self
     }
 
-    pub fn ioapic_read(&self, reg: u32) -> u32 {
-        let base = self.io_apic_addr.as_mut_ptr::<u32>();
+    pub fn ioapic_read(&self, ioapic: usize, reg: u32) -> u32 {
+        let base = self.ioapics[ioapic].addr.as_mut_ptr::<u32>();
         unsafe {
             core::ptr::write_volatile(base, reg);
             core::ptr::read_volatile(base.add(4))
         }
     }
 
-    pub fn ioapic_write(&self, reg: u32, value: u32) {
-        let base = self.io_apic_addr.as_mut_ptr::<u32>();
+    pub fn ioapic_write(&self, ioapic: usize, reg: u32, value: u32) {
+        let base = self.ioapics[ioapic].addr.as_mut_ptr::<u32>();
         unsafe {
             core::ptr::write_volatile(base, reg);
             core::ptr::write_volatile(base.add(4), value);
         }
     }
 
+    fn redirection_register(ioapic: IoApic, gsi: u32) -> u32 {
+        ApicRegister::IoapicRedirectionTableBase as u32 +
+            (gsi - ioapic.gsi_base) * 2
+    }
+
+    /// IOAPIC handling `gsi` and low register of its redirection entry.
+    fn redirection(&self, gsi: u32) -> Option<(usize, u32)> {
+        let idx = self.ioapics[..self.ioapic_count]
+            .iter()
+            .position(|ioapic| ioapic.handles(gsi))?;
+        Some((idx, Self::redirection_register(self.ioapics[idx], gsi)))
+    }
+
+    /// Route `gsi` to `vector` on LAPIC `dest`, keeping its polarity and
+    /// trigger mode.
+    pub fn ioapic_redirect(
+        &self,
+        gsi: u32,
+        vector: u8,
+        dest: u8,
+        masked: bool,
+    ) {
+        let Some((idx, low)) = self.redirection(gsi) else {
+            log::warn!("no IOAPIC handles GSI {gsi}");
+            return;
+        };
+
+        let mode = ApicValue::RedirectionActiveLow as u32 |
+            ApicValue::RedirectionLevel as u32;
+        let mut value = (self.ioapic_read(idx, low) & mode) | vector as u32;
+        if masked {
+            value |= ApicValue::RedirectionMasked as u32;
+        }
+
+        self.ioapic_write(idx, low + 1, (dest as u32) << 24);
+        self.ioapic_write(idx, low, value);
+    }
+
+    /// Mask or unmask `gsi`, keeping its route.
+    pub fn ioapic_mask(&self, gsi: u32, masked: bool) {
+        let Some((idx, low)) = self.redirection(gsi) else {
+            return;
+        };
+
+        let mut value = self.ioapic_read(idx, low);
+        if masked {
+            value |= ApicValue::RedirectionMasked as u32;
+        } else {
+            value &= !(ApicValue::RedirectionMasked as u32);
+        }
+        self.ioapic_write(idx, low, value);
+    }
+
     pub fn init_counter(&self, periodic: bool, ticks: u32) -> u32 {
         let ptr = self.lapic_addr.as_mut_ptr::<u32>();
         unsafe {
@@ -110,6 +226,21 @@ impl Apic {
         }
     }
 
+    /// Send IPI `command` to LAPIC `dest` and wait for its delivery.
+    pub fn send_ipi(&self, dest: u32, command: u32) {
+        let ptr = self.lapic_addr.as_mut_ptr::<u32>();
+        unsafe {
+            let high = ptr.add(ApicRegister::LapicIcrHigh as usize / 4);
+            let low = ptr.add(ApicRegister::LapicIcrLow as usize / 4);
+            high.write_volatile(dest << 24);
+            low.write_volatile(command);
+
+            while low.read_volatile() & ApicValue::IcrPending as u32 != 0 {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
     pub fn end_interrupt(&self) {
         let ptr = self.lapic_addr.as_mut_ptr::<u32>();
         unsafe {
@@ -1,26 +1,51 @@
 use core::fmt::Write;
 
+#[cfg(feature = "framebuffer")]
 use bootloader_api::info::FrameBufferInfo;
+#[cfg(feature = "framebuffer")]
 use spin::{Mutex, Once};
 
+#[cfg(feature = "framebuffer")]
 use crate::arch::console::framebuffer::FrameBufferWriter;
+#[cfg(feature = "serial")]
+use crate::arch::console::serial::COM1;
 
-pub static LOGGER: Once<Logger> = Once::new();
+pub static LOGGER: Logger = Logger::new();
 
-/// A logger instance protected by a spinlock.
+/// A logger writing on every enabled console.
 #[derive(Debug)]
 pub struct Logger {
-    /// Locked framebuffer writer.
-    pub framebuffer: Mutex<FrameBufferWriter>,
+    /// Locked framebuffer writer, once attached.
+    #[cfg(feature = "framebuffer")]
+    pub framebuffer: Once<Mutex<FrameBufferWriter>>,
 }
 
 impl Logger {
     /// Create a new [`Logger`].
-    pub fn new(framebuffer: &'static mut [u8], info: FrameBufferInfo) -> Self {
+    pub const fn new() -> Self {
         Self {
-            framebuffer: Mutex::new(FrameBufferWriter::new(framebuffer, info)),
+            #[cfg(feature = "framebuffer")]
+            framebuffer: Once::new(),
         }
     }
+
+    /// Mirror records on `framebuffer`.
+    #[cfg(feature = "framebuffer")]
+    pub fn attach_framebuffer(
+        &self,
+        framebuffer: &'static mut [u8],
+        info: FrameBufferInfo,
+    ) {
+        self.framebuffer.call_once(move || {
+            Mutex::new(FrameBufferWriter::new(framebuffer, info))
+        });
+    }
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl log::Log for Logger {
@@ -29,9 +54,19 @@ impl log::Log for Logger {
     }
 
     fn log(&self, record: &log::Record) {
-        let mut framebuffer = self.framebuffer.lock();
-        writeln!(framebuffer, "{:5}: {}", record.level(), record.args())
-            .unwrap();
+        #[cfg(feature = "serial")]
+        {
+            let mut serial = COM1.lock();
+            let _ =
+                writeln!(serial, "{:5}: {}", record.level(), record.args());
+        }
+
+        #[cfg(feature = "framebuffer")]
+        if let Some(framebuffer) = self.framebuffer.get() {
+            let mut framebuffer = framebuffer.lock();
+            writeln!(framebuffer, "{:5}: {}", record.level(), record.args())
+                .unwrap();
+        }
     }
 
     fn flush(&self) {}
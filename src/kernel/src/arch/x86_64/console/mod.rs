@@ -1,19 +1,25 @@
 /// Framebuffer.
+#[cfg(feature = "framebuffer")]
 mod framebuffer;
 
 /// Logger.
 pub mod logger;
 
+/// UART 16550 serial port.
+#[cfg(feature = "serial")]
+pub mod serial;
+
+#[cfg(feature = "framebuffer")]
 use bootloader_api::info::FrameBuffer;
 use log::LevelFilter;
 
-/// Create a new logger based on [`log`].
-pub fn init(framebuffer: FrameBuffer) {
-    let info = framebuffer.info();
-    let buffer = framebuffer.into_buffer();
-
-    let logger =
-        logger::LOGGER.call_once(move || logger::Logger::new(buffer, info));
+/// Install [`logger::LOGGER`] as [`log`] logger.
+///
+/// Does not depend on any other initialization, records go to the serial
+/// port until a framebuffer is attached.
+pub fn init() {
+    #[cfg(feature = "serial")]
+    serial::COM1.lock().init();
 
     let level = if cfg!(debug_assertions) {
         LevelFilter::Debug
@@ -21,7 +27,16 @@ pub fn init(framebuffer: FrameBuffer) {
         LevelFilter::Info
     };
 
-    let _ = log::set_logger(logger);
+    let _ = log::set_logger(&logger::LOGGER);
     log::set_max_level(level);
+}
+
+/// Mirror logs on the bootloader framebuffer.
+#[cfg(feature = "framebuffer")]
+pub fn attach_framebuffer(framebuffer: FrameBuffer) {
+    let info = framebuffer.info();
+    let buffer = framebuffer.into_buffer();
+
+    logger::LOGGER.attach_framebuffer(buffer, info);
     log::info!("framebuffer : {info:?}");
 }
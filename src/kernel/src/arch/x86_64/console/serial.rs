@@ -0,0 +1,105 @@
+use core::fmt;
+
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+/// COM1 I/O base.
+const COM1_BASE: u16 = 0x3F8;
+
+/// Data register, divisor latch low byte when DLAB is set.
+const DATA: u16 = 0;
+/// Interrupt enable register, divisor latch high byte when DLAB is set.
+const INTERRUPT_ENABLE: u16 = 1;
+/// FIFO control register.
+const FIFO_CONTROL: u16 = 2;
+/// Line control register.
+const LINE_CONTROL: u16 = 3;
+/// Modem control register.
+const MODEM_CONTROL: u16 = 4;
+/// Line status register.
+const LINE_STATUS: u16 = 5;
+
+/// Divisor latch access bit.
+const LCR_DLAB: u8 = 0x80;
+/// 8 data bits, no parity, one stop bit.
+const LCR_8N1: u8 = 0x03;
+/// Enable and clear FIFOs, 14 bytes threshold.
+const FCR_ENABLE: u8 = 0xC7;
+/// DTR, RTS and OUT2.
+const MCR_READY: u8 = 0x0B;
+/// Transmitter holding register empty.
+const LSR_THR_EMPTY: u8 = 0x20;
+
+/// Divisor for 115200 bauds.
+const BAUD_DIVISOR: u16 = 1;
+
+/// Locked COM1 port.
+pub static COM1: Mutex<SerialPort> = Mutex::new(SerialPort::new(COM1_BASE));
+
+/// UART 16550 serial port.
+#[derive(Debug)]
+pub struct SerialPort {
+    base: u16,
+}
+
+impl SerialPort {
+    /// Create a [`SerialPort`] on I/O `base`.
+    pub const fn new(base: u16) -> Self {
+        Self { base }
+    }
+
+    fn read_reg(&self, reg: u16) -> u8 {
+        unsafe { Port::<u8>::new(self.base + reg).read() }
+    }
+
+    fn write_reg(&mut self, reg: u16, value: u8) {
+        unsafe { Port::<u8>::new(self.base + reg).write(value) }
+    }
+
+    /// Program 115200 bauds 8N1 with FIFOs and no interrupts.
+    pub fn init(&mut self) {
+        self.write_reg(INTERRUPT_ENABLE, 0x00);
+
+        self.write_reg(LINE_CONTROL, LCR_DLAB);
+        self.write_reg(DATA, BAUD_DIVISOR as u8);
+        self.write_reg(INTERRUPT_ENABLE, (BAUD_DIVISOR >> 8) as u8);
+        self.write_reg(LINE_CONTROL, LCR_8N1);
+
+        self.write_reg(FIFO_CONTROL, FCR_ENABLE);
+        self.write_reg(MODEM_CONTROL, MCR_READY);
+    }
+
+    /// Send one byte, waiting for the transmitter.
+    pub fn write_byte(&mut self, byte: u8) {
+        while self.read_reg(LINE_STATUS) & LSR_THR_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        self.write_reg(DATA, byte);
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+/// Print on [`COM1`].
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    let _ = COM1.lock().write_fmt(args);
+}
+
+/// Print on COM1 without taking [`COM1`] lock.
+/// Panicking code may hold it.
+pub fn panic_print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    let _ = SerialPort::new(COM1_BASE).write_fmt(args);
+}
@@ -6,6 +6,10 @@ pub enum ApicRegister {
     LapicSivr = 0xF0,
     /// End of interrupt register (EOI).
     LapicEoi = 0xB0,
+    /// Interrupt command register (ICR), low word.
+    LapicIcrLow = 0x300,
+    /// Interrupt command register (ICR), destination word.
+    LapicIcrHigh = 0x310,
 
     /// Local vector table timer (LVTT).
     LapicLvtt = 0x320,
@@ -34,4 +38,18 @@ pub enum ApicValue {
     TdcrDivideBy1 = 0x1,
     /// Enable LAPIC.
     SvrEnable = 0x100,
+    /// Fixed IPI, asserted, vector in the low byte.
+    IcrFixed = 0x4000,
+    /// INIT IPI, asserted.
+    IcrInit = 0x4500,
+    /// Startup IPI, vector is the trampoline page number.
+    IcrStartup = 0x4600,
+    /// IPI not accepted yet.
+    IcrPending = 0x1000,
+    /// Active low IOAPIC redirection entry.
+    RedirectionActiveLow = 0x2000,
+    /// Level triggered IOAPIC redirection entry.
+    RedirectionLevel = 0x8000,
+    /// Masked IOAPIC redirection entry.
+    RedirectionMasked = 0x1_0000,
 }
@@ -10,4 +10,15 @@ pub enum IstIndex {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IdtIndex {
     Timer = 0x20,
+    /// First IOAPIC line, one vector per GSI.
+    Irq = 0x30,
+    /// Switch to an earlier deadline thread.
+    Reschedule = 0xF0,
+    /// Queue threads woken up by another core.
+    Wake = 0xF1,
+    /// Invalidate a page unmapped by another core.
+    TlbShootdown = 0xF2,
 }
+
+/// Number of GSIs routed to [`IdtIndex::Irq`] vectors.
+pub const MAX_IRQS: usize = 24;
@@ -0,0 +1,101 @@
+//! Interrupt entry stubs.
+//!
+//! Every vector, but IST ones, enters through a stub pushing a full
+//! [`TrapFrame`] on the kernel stack. The frame of an interrupted user
+//! thread is copied in its [`Tcb`] before any scheduling decision, so that
+//! a handler may switch to another thread and never return. Otherwise the
+//! frame goes back through [`trap_exit`], shared with context switches.
+//!
+//! [`Tcb`]: crate::objects::tcb::Tcb
+
+use core::arch::naked_asm;
+use core::mem::offset_of;
+
+use x86_64::VirtAddr;
+
+use super::{exception, idt};
+use crate::arch::percpu;
+use crate::arch::trapframe::{TrapFrame, trap_exit};
+
+/// Number of vectors reserved for exceptions.
+const EXCEPTIONS: u8 = 32;
+
+/// Entry stub of vector `V`, without CPU pushed error code.
+#[unsafe(naked)]
+pub(super) extern "C" fn stub<const V: u8>() {
+    naked_asm!(
+        "push 0",
+        "push r15",
+        "mov r15d, {vector}",
+        "jmp {common}",
+        vector = const V,
+        common = sym interrupt_common,
+    )
+}
+
+/// Entry stub of vector `V`, the CPU pushing an error code.
+#[unsafe(naked)]
+pub(super) extern "C" fn stub_with_code<const V: u8>() {
+    naked_asm!(
+        "push r15",
+        "mov r15d, {vector}",
+        "jmp {common}",
+        vector = const V,
+        common = sym interrupt_common,
+    )
+}
+
+/// Address of entry stub `stub`, for IDT gates.
+pub(super) fn addr(stub: extern "C" fn()) -> VirtAddr {
+    VirtAddr::new(stub as usize as u64)
+}
+
+/// Common path of entry stubs, R15 being saved and holding the vector.
+#[unsafe(naked)]
+extern "C" fn interrupt_common() {
+    naked_asm!(
+        "push r14",
+        "push r13",
+        "push r12",
+        "push r11",
+        "push r10",
+        "push r9",
+        "push r8",
+        "push rbp",
+        "push rdi",
+        "push rsi",
+        "push rdx",
+        "push rcx",
+        "push rbx",
+        "push rax",
+        // Kernel GS base when coming from user, see `percpu`.
+        "test qword ptr [rsp + {cs}], 3",
+        "jz 2f",
+        "swapgs",
+        "2:",
+        "mov rdi, rsp",
+        "mov esi, r15d",
+        // Frame is 8 bytes off the 16 bytes alignment of the entry.
+        "sub rsp, 8",
+        "call {entry}",
+        "add rsp, 8",
+        "jmp {exit}",
+        cs = const offset_of!(TrapFrame, cs),
+        entry = sym interrupt_entry,
+        exit = sym trap_exit,
+    )
+}
+
+extern "C" fn interrupt_entry(frame: &mut TrapFrame, vector: u8) {
+    if frame.cs & 3 == 3 &&
+        let Some(current) = percpu::current()
+    {
+        // SAFETY: current thread is valid while it runs.
+        unsafe { (*current.as_ptr()).context = *frame };
+    }
+
+    match vector {
+        0..EXCEPTIONS => exception::handle(frame, vector),
+        _ => idt::handle(vector),
+    }
+}
@@ -0,0 +1,141 @@
+//! Architectural exceptions.
+//!
+//! Exceptions raised by user code become a [`Fault::UserException`], or a
+//! [`Fault::VmFault`] for page faults, sent to the fault endpoint of the
+//! faulting thread, which waits for the handler reply. A page fault
+//! handler acts as a pager: once it replies, the faulting instruction runs
+//! again. Kernel exceptions are fatal, but breakpoints.
+//!
+//! Double fault, NMI and machine check run on IST stacks and keep their own
+//! handlers.
+
+use x86_64::PrivilegeLevel;
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::{InterruptDescriptorTable, PageFaultErrorCode};
+
+use super::entry::{addr, stub, stub_with_code};
+use crate::arch::trapframe::TrapFrame;
+use crate::objects::endpoint::send_fault_ipc;
+use crate::objects::tcb::Fault;
+use crate::scheduler;
+
+const BREAKPOINT: u8 = 3;
+const PAGE_FAULT: u8 = 14;
+
+/// Install exception stubs, but those running on IST stacks.
+pub(super) fn set_handlers(idt: &mut InterruptDescriptorTable) {
+    // SAFETY: stubs save and restore the interrupted context.
+    unsafe {
+        idt.divide_error.set_handler_addr(addr(stub::<0>));
+        idt.debug.set_handler_addr(addr(stub::<1>));
+        // User code may `int3`.
+        idt.breakpoint
+            .set_handler_addr(addr(stub::<BREAKPOINT>))
+            .set_privilege_level(PrivilegeLevel::Ring3);
+        idt.overflow.set_handler_addr(addr(stub::<4>));
+        idt.bound_range_exceeded.set_handler_addr(addr(stub::<5>));
+        idt.invalid_opcode.set_handler_addr(addr(stub::<6>));
+        idt.device_not_available.set_handler_addr(addr(stub::<7>));
+        idt.invalid_tss.set_handler_addr(addr(stub_with_code::<10>));
+        idt.segment_not_present
+            .set_handler_addr(addr(stub_with_code::<11>));
+        idt.stack_segment_fault
+            .set_handler_addr(addr(stub_with_code::<12>));
+        idt.general_protection_fault
+            .set_handler_addr(addr(stub_with_code::<13>));
+        idt.page_fault
+            .set_handler_addr(addr(stub_with_code::<PAGE_FAULT>));
+        idt.x87_floating_point.set_handler_addr(addr(stub::<16>));
+        idt.alignment_check
+            .set_handler_addr(addr(stub_with_code::<17>));
+        idt.simd_floating_point.set_handler_addr(addr(stub::<19>));
+        idt.virtualization.set_handler_addr(addr(stub::<20>));
+        idt.cp_protection_exception
+            .set_handler_addr(addr(stub_with_code::<21>));
+        idt.hv_injection_exception
+            .set_handler_addr(addr(stub::<28>));
+        idt.vmm_communication_exception
+            .set_handler_addr(addr(stub_with_code::<29>));
+        idt.security_exception
+            .set_handler_addr(addr(stub_with_code::<30>));
+    }
+}
+
+fn name(vector: u8) -> &'static str {
+    match vector {
+        0 => "Divide error (#DE)",
+        1 => "Debug (#DB)",
+        3 => "Breakpoint (#BP)",
+        4 => "Overflow (#OF)",
+        5 => "Bound range exceeded (#BR)",
+        6 => "Invalid opcode (#UD)",
+        7 => "Device not available (#NM)",
+        10 => "Invalid TSS (#TS)",
+        11 => "Segment not present (#NP)",
+        12 => "Stack segment fault (#SS)",
+        13 => "General protection fault (#GP)",
+        14 => "Page fault (#PF)",
+        16 => "x87 floating point (#MF)",
+        17 => "Alignment check (#AC)",
+        19 => "SIMD floating point (#XM)",
+        20 => "Virtualization (#VE)",
+        21 => "Control protection (#CP)",
+        28 => "Hypervisor injection (#HV)",
+        29 => "VMM communication (#VC)",
+        30 => "Security (#SX)",
+        _ => "Reserved exception",
+    }
+}
+
+/// Handle exception `vector`, raised in `frame`.
+///
+/// A user frame is already saved in the current thread.
+pub(super) fn handle(frame: &mut TrapFrame, vector: u8) {
+    let (rip, code) = (frame.rip, frame.error_code);
+
+    if frame.cs & 3 != 3 {
+        if vector == BREAKPOINT {
+            log::error!("{} at {rip:#x}", name(vector));
+            return;
+        }
+
+        let (rsp, registers) = (frame.rsp, frame.registers);
+        let address = match vector {
+            PAGE_FAULT => Cr2::read_raw(),
+            _ => 0,
+        };
+        panic!(
+            "{} at {rip:#x}, code {code:#x}, address {address:#x}, rsp \
+             {rsp:#x}, registers {registers:x?}",
+            name(vector)
+        );
+    }
+
+    let _lock = scheduler::lock::acquire();
+    // SAFETY: exceptions run with interrupts disabled.
+    let executor = unsafe { scheduler::executor() };
+    let Some(current) = executor.current() else {
+        panic!("{} at {rip:#x} without current thread", name(vector));
+    };
+
+    let fault = match vector {
+        PAGE_FAULT => Fault::VmFault {
+            addr: Cr2::read_raw() as usize,
+            fsr: code,
+            instruction_fault: PageFaultErrorCode::from_bits_truncate(
+                code as u64,
+            )
+            .contains(PageFaultErrorCode::INSTRUCTION_FETCH),
+        },
+        _ => Fault::UserException {
+            number: vector as usize,
+            code,
+        },
+    };
+    unsafe {
+        if send_fault_ipc(current, fault).is_err() {
+            log::warn!("thread stopped on {fault:x?}, no fault handler");
+        }
+        executor.block_current(current.as_ref().state)
+    }
+}
@@ -1,93 +1,111 @@
 use core::ptr::addr_of;
 
-use spin::Lazy;
+use spin::Once;
 use x86_64::VirtAddr;
-use x86_64::registers::segmentation::{DS, ES, FS, GS, SS, SegmentSelector};
+use x86_64::registers::segmentation::{DS, ES, FS, SS, SegmentSelector};
 use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable};
 use x86_64::structures::tss::TaskStateSegment;
 
 use crate::arch::constants::interrupts::IstIndex;
+use crate::scheduler::MAX_CPUS;
 
 const STACK_SIZE: usize = 4096 * 2; // 8KiB.
 
-/// Task state segment.
-pub static TSS: Lazy<TaskStateSegment> = Lazy::new(|| {
-    let mut tss = TaskStateSegment::new();
+/// IST stacks and privilege stack of a core.
+const STACKS_PER_CORE: usize = 4;
 
-    // We MUST avoid using same stack.
-    tss.interrupt_stack_table[IstIndex::DoubleFault as usize] = {
-        static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-        let stack_start = VirtAddr::from_ptr(addr_of!(STACK));
-        stack_start + STACK_SIZE as u64
-    };
+#[repr(C, align(16))]
+struct Stack([u8; STACK_SIZE]);
 
-    tss.interrupt_stack_table[IstIndex::NonMaskableInterrupt as usize] = {
-        static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-        let stack_start = VirtAddr::from_ptr(addr_of!(STACK));
-        stack_start + STACK_SIZE as u64
-    };
+static mut STACKS: [[Stack; STACKS_PER_CORE]; MAX_CPUS] =
+    [const { [const { Stack([0; STACK_SIZE]) }; STACKS_PER_CORE] }; MAX_CPUS];
 
-    tss.interrupt_stack_table[IstIndex::MachineCheck as usize] = {
-        static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-        let stack_start = VirtAddr::from_ptr(addr_of!(STACK));
-        stack_start + STACK_SIZE as u64
-    };
+/// Task state segments, one per core since a loaded TSS is marked busy.
+static mut TSS: [TaskStateSegment; MAX_CPUS] =
+    [const { TaskStateSegment::new() }; MAX_CPUS];
 
-    // Privilege stack table for userland calls.
-    tss.privilege_stack_table[0] = {
-        static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-        let stack_start = VirtAddr::from_ptr(addr_of!(STACK));
-        stack_start + STACK_SIZE as u64
-    };
+static mut GDT: [GlobalDescriptorTable; MAX_CPUS] =
+    [const { GlobalDescriptorTable::new() }; MAX_CPUS];
 
-    tss
-});
-
-pub static GDT: Lazy<(GlobalDescriptorTable, Selectors)> = Lazy::new(|| {
-    let mut gdt = GlobalDescriptorTable::new();
-    let code_selector = gdt.append(Descriptor::kernel_code_segment());
-    let data_selector = gdt.append(Descriptor::kernel_data_segment());
-    let user_code = gdt.append(Descriptor::user_code_segment());
-    let user_data = gdt.append(Descriptor::user_data_segment());
-    let tss_selector = gdt.append(Descriptor::tss_segment(&TSS));
-    (
-        gdt,
-        Selectors {
-            code_selector,
-            data_selector,
-            user_code_selector: user_code,
-            user_data_selector: user_data,
-            tss_selector,
-        },
-    )
-});
+/// Selectors, identical on every core.
+static SELECTORS: Once<Selectors> = Once::new();
 
 /// Kernel segment selectors.
-#[derive(Debug)]
+///
+/// User data comes before user code, `sysret` loads them from consecutive
+/// descriptors.
+#[derive(Debug, Clone, Copy)]
 pub struct Selectors {
     pub code_selector: SegmentSelector,
-    data_selector: SegmentSelector,
+    pub data_selector: SegmentSelector,
+    pub user_data_selector: SegmentSelector,
     pub user_code_selector: SegmentSelector,
-    user_data_selector: SegmentSelector,
     tss_selector: SegmentSelector,
 }
 
-/// Loads the GDT into the CPU.
+/// Segment selectors, once a core loaded its GDT.
+pub fn selectors() -> &'static Selectors {
+    SELECTORS.get().expect("GDT not loaded")
+}
+
+/// Fill `tss` with `stacks`.
+fn init_tss(tss: &mut TaskStateSegment, stacks: &[Stack; STACKS_PER_CORE]) {
+    let top = |stack: &Stack| VirtAddr::from_ptr(stack) + STACK_SIZE as u64;
+
+    // We MUST avoid using same stack.
+    tss.interrupt_stack_table[IstIndex::DoubleFault as usize] =
+        top(&stacks[0]);
+    tss.interrupt_stack_table[IstIndex::NonMaskableInterrupt as usize] =
+        top(&stacks[1]);
+    tss.interrupt_stack_table[IstIndex::MachineCheck as usize] =
+        top(&stacks[2]);
+
+    // Privilege stack table for userland calls.
+    tss.privilege_stack_table[0] = top(&stacks[3]);
+}
+
+/// Loads current core GDT into the CPU.
+///
+/// Must be called once per core, after its per-CPU area is installed.
 pub fn load() {
     use x86_64::instructions::segmentation::{CS, Segment};
     use x86_64::instructions::tables::load_tss;
 
-    GDT.0.load();
+    let cpu = crate::arch::cpuid();
+    assert!(cpu < MAX_CPUS, "core {cpu} has no GDT");
+
+    // SAFETY: each core only builds its own tables, before loading them.
+    let (gdt, selectors) = unsafe {
+        let tss = &mut *(&raw mut TSS[cpu]);
+        init_tss(tss, &*addr_of!(STACKS[cpu]));
+
+        let gdt = &mut *(&raw mut GDT[cpu]);
+        let code_selector = gdt.append(Descriptor::kernel_code_segment());
+        let data_selector = gdt.append(Descriptor::kernel_data_segment());
+        let user_data = gdt.append(Descriptor::user_data_segment());
+        let user_code = gdt.append(Descriptor::user_code_segment());
+        let tss_selector = gdt.append(Descriptor::tss_segment(tss));
+        let selectors = Selectors {
+            code_selector,
+            data_selector,
+            user_data_selector: user_data,
+            user_code_selector: user_code,
+            tss_selector,
+        };
+        (&*gdt, selectors)
+    };
+
+    gdt.load();
+    SELECTORS.call_once(|| selectors);
 
-    // Flat model.
+    // Flat model. GS is left alone, loading it clears the per-CPU area base.
     unsafe {
-        CS::set_reg(GDT.1.code_selector);
-        SS::set_reg(GDT.1.data_selector);
-        DS::set_reg(GDT.1.data_selector);
-        ES::set_reg(GDT.1.data_selector);
-        FS::set_reg(GDT.1.data_selector);
-        GS::set_reg(GDT.1.data_selector);
-
-        load_tss(GDT.1.tss_selector);
+        CS::set_reg(selectors.code_selector);
+        SS::set_reg(selectors.data_selector);
+        DS::set_reg(selectors.data_selector);
+        ES::set_reg(selectors.data_selector);
+        FS::set_reg(selectors.data_selector);
+
+        load_tss(selectors.tss_selector);
     }
 }
@@ -1,17 +1,25 @@
 use lazy_static::lazy_static;
-use x86_64::structures::idt::{
-    InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode,
-};
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
 
+use super::entry::{addr, stub};
+use super::exception;
 use crate::arch::constants::interrupts::*;
-use crate::{APIC, TICKS};
+use crate::arch::{ipi, percpu, tick};
+use crate::{APIC, TICKS, scheduler};
+
+/// IOAPIC line stubs, indexed by GSI.
+macro_rules! irq_stubs {
+    ($($gsi:literal)*) => {
+        [$(stub::<{ IdtIndex::Irq as u8 + $gsi }> as extern "C" fn()),*]
+    };
+}
 
 lazy_static! {
     pub(super) static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
 
         // Reserved vectors.
-        idt.breakpoint.set_handler_fn(breakpoint_exception);
+        exception::set_handlers(&mut idt);
         unsafe {
             idt.non_maskable_interrupt
                 .set_handler_fn(non_maskable_interrupt)
@@ -22,26 +30,38 @@ lazy_static! {
                 .set_handler_fn(double_fault)
                 .set_stack_index(IstIndex::DoubleFault as u16);
         }
-        idt.page_fault.set_handler_fn(page_fault);
         unsafe {
             idt.machine_check
                 .set_handler_fn(machine_check)
                 .set_stack_index(IstIndex::MachineCheck as u16);
         }
 
-        // Custom vectors.
-        idt[IdtIndex::Timer as u8].set_handler_fn(timer_handler);
+        // Custom vectors, see `handle`.
+        let irqs: [_; MAX_IRQS] = irq_stubs!(
+            0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23
+        );
+        // SAFETY: stubs save and restore the interrupted context.
+        unsafe {
+            idt[IdtIndex::Timer as u8]
+                .set_handler_addr(addr(stub::<{ IdtIndex::Timer as u8 }>));
+            for (gsi, stub) in irqs.into_iter().enumerate() {
+                idt[IdtIndex::Irq as u8 + gsi as u8]
+                    .set_handler_addr(addr(stub));
+            }
+            idt[IdtIndex::Reschedule as u8].set_handler_addr(addr(
+                stub::<{ IdtIndex::Reschedule as u8 }>,
+            ));
+            idt[IdtIndex::Wake as u8]
+                .set_handler_addr(addr(stub::<{ IdtIndex::Wake as u8 }>));
+            idt[IdtIndex::TlbShootdown as u8].set_handler_addr(addr(
+                stub::<{ IdtIndex::TlbShootdown as u8 }>,
+            ));
+        }
 
         idt
     };
 }
 
-extern "x86-interrupt" fn breakpoint_exception(
-    stack_frame: InterruptStackFrame,
-) {
-    log::error!("Breakpoint (#BP) Exception: {stack_frame:?}");
-}
-
 extern "x86-interrupt" fn non_maskable_interrupt(
     stack_frame: InterruptStackFrame,
 ) {
@@ -55,32 +75,69 @@ extern "x86-interrupt" fn double_fault(
     panic!("Double fault: {:#?}", stack_frame);
 }
 
-extern "x86-interrupt" fn page_fault(
+extern "x86-interrupt" fn machine_check(
     stack_frame: InterruptStackFrame,
-    error_code: PageFaultErrorCode,
-) {
-    use x86_64::registers::control::Cr2;
+) -> ! {
+    panic!("Machine check: {:#?}", stack_frame);
+}
+
+/// Handle device or IPI interrupt `vector`.
+pub(super) fn handle(vector: u8) {
+    const TIMER: u8 = IdtIndex::Timer as u8;
+    const IRQ: u8 = IdtIndex::Irq as u8;
+    const IRQ_END: u8 = IRQ + MAX_IRQS as u8;
+    const RESCHEDULE: u8 = IdtIndex::Reschedule as u8;
+    const WAKE: u8 = IdtIndex::Wake as u8;
+    const TLB_SHOOTDOWN: u8 = IdtIndex::TlbShootdown as u8;
 
-    let pfla = match Cr2::read() {
-        Ok(addr) => addr.as_u64(),
-        Err(_) => 0,
+    match vector {
+        TIMER => timer(),
+        IRQ..IRQ_END => irq((vector - IRQ) as u32),
+        RESCHEDULE => reschedule(),
+        WAKE => wake(),
+        TLB_SHOOTDOWN => tlb_shootdown(),
+        _ => log::warn!("unexpected interrupt {vector:#x}"),
+    }
+}
+
+fn timer() {
+    let _lock = scheduler::lock::acquire();
+    // Calibrated timers tick on every core, each counting its own.
+    let preempt = if tick::period().is_some() {
+        percpu::count_tick();
+        true
+    } else {
+        TICKS.lock().tick_handler()
     };
+    APIC.lock().end_interrupt();
 
-    log::error!(
-        "Page fault ({:?}) at {:?}: {:?}",
-        error_code,
-        pfla,
-        stack_frame
-    );
+    if preempt {
+        unsafe { scheduler::executor().preempt() };
+    }
 }
 
-extern "x86-interrupt" fn machine_check(
-    stack_frame: InterruptStackFrame,
-) -> ! {
-    panic!("Machine check: {:#?}", stack_frame);
+fn irq(gsi: u32) {
+    let _lock = scheduler::lock::acquire();
+    // Masked until user code acks its handler.
+    APIC.lock().ioapic_mask(gsi, true);
+    unsafe { crate::objects::irq::handle(gsi as usize) };
+    APIC.lock().end_interrupt();
+}
+
+fn reschedule() {
+    let _lock = scheduler::lock::acquire();
+    APIC.lock().end_interrupt();
+    unsafe { scheduler::executor().reschedule() };
+}
+
+fn wake() {
+    let _lock = scheduler::lock::acquire();
+    APIC.lock().end_interrupt();
+    unsafe { scheduler::wake_remote() };
 }
 
-extern "x86-interrupt" fn timer_handler(_stack_frame: InterruptStackFrame) {
-    TICKS.lock().tick_handler();
+fn tlb_shootdown() {
+    // Served without the kernel lock, its initiator may hold it.
+    ipi::handle_shootdown();
     APIC.lock().end_interrupt();
 }
@@ -1,6 +1,12 @@
 /// Global descriptor table.
 pub mod gdt;
 
+/// Interrupt entry stubs.
+mod entry;
+
+/// Architectural exceptions.
+mod exception;
+
 /// Interrupt descriptor table.
 mod idt;
 
@@ -0,0 +1,146 @@
+//! Inter-processor interrupts.
+//!
+//! Cores are addressed by their logical index, see [`super::percpu`]. A
+//! core only receives IPIs once it called [`init`], when its executor is
+//! ready.
+//!
+//! A TLB shootdown is synchronous: its initiator waits until every other
+//! core invalidated the page. Initiators are serialized and serve pending
+//! requests while waiting for their turn, so two cores unmapping at the
+//! same time, with interrupts disabled, do not wait on each other.
+
+use core::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+
+use spin::Mutex;
+
+use crate::APIC;
+use crate::arch::constants::apic::ApicValue;
+use crate::arch::constants::interrupts::IdtIndex;
+use crate::arch::vspace::{flush_asid, flush_asid_page};
+use crate::arch::{VirtAddr, percpu};
+use crate::objects::vspace::Asid;
+use crate::scheduler::MAX_CPUS;
+
+/// IPI kinds, each with its own vector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ipi {
+    Reschedule,
+    Wake,
+    TlbShootdown,
+}
+
+impl Ipi {
+    pub const fn vector(self) -> IdtIndex {
+        match self {
+            Self::Reschedule => IdtIndex::Reschedule,
+            Self::Wake => IdtIndex::Wake,
+            Self::TlbShootdown => IdtIndex::TlbShootdown,
+        }
+    }
+}
+
+/// Cores accepting IPIs, one bit per core.
+static ONLINE: AtomicU64 = AtomicU64::new(0);
+
+/// Held by the core running a shootdown.
+static SHOOTDOWN: Mutex<()> = Mutex::new(());
+/// Page to invalidate, or [`ALL_PAGES`].
+static SHOOTDOWN_VADDR: AtomicU64 = AtomicU64::new(0);
+/// ASID of the pages to invalidate.
+static SHOOTDOWN_ASID: AtomicU16 = AtomicU16::new(0);
+/// Cores which did not invalidate the page yet, one bit per core.
+static SHOOTDOWN_PENDING: AtomicU64 = AtomicU64::new(0);
+
+/// Shootdown address standing for every page of the ASID, never a user one.
+const ALL_PAGES: u64 = u64::MAX;
+
+const _: () = assert!(MAX_CPUS <= u64::BITS as usize);
+
+#[inline]
+fn current() -> u64 {
+    1 << super::cpuid()
+}
+
+/// Accept IPIs on current core.
+pub fn init() {
+    ONLINE.fetch_or(current(), Ordering::Release);
+}
+
+/// Whether `cpu` accepts IPIs.
+pub fn is_online(cpu: usize) -> bool {
+    cpu < MAX_CPUS && ONLINE.load(Ordering::Acquire) & 1 << cpu != 0
+}
+
+/// Send `ipi` to `cpu`.
+pub fn send(cpu: usize, ipi: Ipi) {
+    let Some(apic_id) = percpu::apic_id_of(cpu) else {
+        log::warn!("{ipi:?} IPI to unknown core {cpu}");
+        return;
+    };
+    let command = ApicValue::IcrFixed as u32 | ipi.vector() as u32;
+    APIC.lock().send_ipi(apic_id, command);
+}
+
+/// Invalidate `vaddr` of `asid` on every online core, current one
+/// included.
+///
+/// Any core may run a thread of the address space `vaddr` belongs to, or
+/// keep its TLB entries tagged.
+pub fn shootdown(asid: Asid, vaddr: VirtAddr) {
+    broadcast(asid, vaddr.as_u64());
+}
+
+/// Invalidate every page of `asid` on every online core, current one
+/// included, before the ASID is reused.
+pub fn shootdown_asid(asid: Asid) {
+    broadcast(asid, ALL_PAGES);
+}
+
+fn flush(asid: Asid, vaddr: u64) {
+    match vaddr {
+        ALL_PAGES => flush_asid(asid),
+        _ => flush_asid_page(asid, VirtAddr::new(vaddr)),
+    }
+}
+
+fn broadcast(asid: Asid, vaddr: u64) {
+    flush(asid, vaddr);
+
+    let targets = ONLINE.load(Ordering::Acquire) & !current();
+    if targets == 0 {
+        return;
+    }
+
+    let _guard = loop {
+        if let Some(guard) = SHOOTDOWN.try_lock() {
+            break guard;
+        }
+        handle_shootdown();
+        core::hint::spin_loop();
+    };
+
+    SHOOTDOWN_VADDR.store(vaddr, Ordering::Relaxed);
+    SHOOTDOWN_ASID.store(asid, Ordering::Relaxed);
+    SHOOTDOWN_PENDING.store(targets, Ordering::Release);
+    for cpu in 0..MAX_CPUS {
+        if targets & 1 << cpu != 0 {
+            send(cpu, Ipi::TlbShootdown);
+        }
+    }
+
+    while SHOOTDOWN_PENDING.load(Ordering::Acquire) != 0 {
+        core::hint::spin_loop();
+    }
+}
+
+/// Invalidate the pages of the running shootdown, if current core has not
+/// yet.
+pub fn handle_shootdown() {
+    let cpu = current();
+    if SHOOTDOWN_PENDING.load(Ordering::Acquire) & cpu != 0 {
+        let vaddr = SHOOTDOWN_VADDR.load(Ordering::Relaxed);
+        let asid = SHOOTDOWN_ASID.load(Ordering::Relaxed);
+        flush(asid, vaddr);
+        SHOOTDOWN_PENDING.fetch_and(!cpu, Ordering::Release);
+    }
+}
@@ -5,7 +5,7 @@ pub mod apic;
 pub mod acpi;
 
 /// Console logger.
-#[cfg(feature = "framebuffer")]
+#[cfg(any(feature = "framebuffer", feature = "serial"))]
 pub mod console;
 
 /// x86 constants.
@@ -14,12 +14,28 @@ pub mod constants;
 /// Interrupt descriptor table for CPU interrupts.
 pub mod interrupts;
 
+/// Inter-processor interrupts.
+pub mod ipi;
+
+/// Per-CPU area.
+pub mod percpu;
+
 /// Programmable interrupt controller.
 pub mod pic;
 
 /// Programmable interval timer.
 pub mod pit;
 
+/// Application processors bring-up.
+pub mod smp;
+
+/// QEMU debug devices for the test framework.
+#[cfg(test)]
+pub mod qemu;
+
+/// Processor topology.
+pub mod topology;
+
 /// Handle PIT or LAPIC timer.
 pub mod tick;
 
@@ -41,21 +57,30 @@ pub fn halt() {
 
 /// System data.
 pub struct System {
+    pub packages: u32,
     pub cores: u32,
+    /// Enabled logical processors, each with its own scheduler.
+    pub threads: u32,
 }
 
 /// Return system data.
 pub fn sysinfo() -> System {
-    let cores = {
-        use core::arch::x86_64::__cpuid;
-        let cpuid = __cpuid(4);
-        ((cpuid.eax >> 26) & 0x3f) + 1
-    };
-    System { cores }
+    let topology = topology::topology();
+    System {
+        packages: topology.packages(),
+        cores: topology.cores(),
+        threads: topology.threads(),
+    }
+}
+
+/// Return current core logical index.
+#[inline]
+pub fn cpuid() -> usize {
+    percpu::index()
 }
 
-/// Return current core ID.
-pub fn cpuid() -> u32 {
+/// Return current core APIC ID, through the serializing `cpuid`.
+pub fn apic_id() -> u32 {
     let cpuid = core::arch::x86_64::__cpuid(1);
     (cpuid.ebx >> 24) & 0xff
 }
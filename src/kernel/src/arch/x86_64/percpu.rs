@@ -0,0 +1,172 @@
+//! Per-CPU area, reached through GS base.
+//!
+//! GS base holds this core [`PerCpu`] while in kernel and the user value
+//! otherwise, `swapgs` switches both on kernel entry and exit.
+//!
+//! Cores are identified by a dense logical index, the boot core being 0,
+//! rather than by their APIC ID.
+
+use core::arch::asm;
+use core::mem::offset_of;
+use core::ptr::NonNull;
+
+use x86_64::VirtAddr;
+use x86_64::registers::model_specific::{GsBase, KernelGsBase};
+
+use crate::objects::tcb::Tcb;
+use crate::scheduler::MAX_CPUS;
+use crate::scheduler::executor::Executor;
+
+/// Kernel stack size, per core.
+const KERNEL_STACK_SIZE: usize = 4096 * 4;
+
+/// Offset of [`PerCpu::kernel_stack`], for entry stubs.
+pub const KERNEL_STACK: usize = offset_of!(PerCpu, kernel_stack);
+/// Offset of [`PerCpu::user_rsp`], for entry stubs.
+pub const USER_RSP: usize = offset_of!(PerCpu, user_rsp);
+/// Offset of [`PerCpu::current`], for entry stubs.
+pub const CURRENT: usize = offset_of!(PerCpu, current);
+/// Offset of [`PerCpu::this`].
+const THIS: usize = offset_of!(PerCpu, this);
+
+/// Core private data.
+#[derive(Debug)]
+#[repr(C, align(64))]
+pub struct PerCpu {
+    /// Top of this core kernel stack.
+    pub kernel_stack: u64,
+    /// User RSP, saved on syscall entry.
+    pub user_rsp: u64,
+    /// Address of this area, GS base cannot be read in place.
+    this: *mut PerCpu,
+    /// Logical core index.
+    pub index: usize,
+    /// LAPIC ID.
+    pub apic_id: u32,
+    /// Thread running on this core.
+    pub current: Option<NonNull<Tcb>>,
+    /// This core executor, once its scheduler runs.
+    pub executor: Option<NonNull<Executor>>,
+    /// Timer ticks counted on this core.
+    pub ticks: u64,
+}
+
+impl PerCpu {
+    const fn new() -> Self {
+        Self {
+            kernel_stack: 0,
+            user_rsp: 0,
+            this: core::ptr::null_mut(),
+            index: 0,
+            apic_id: u32::MAX,
+            current: None,
+            executor: None,
+            ticks: 0,
+        }
+    }
+}
+
+#[repr(C, align(16))]
+struct KernelStack([u8; KERNEL_STACK_SIZE]);
+
+static mut AREAS: [PerCpu; MAX_CPUS] = [const { PerCpu::new() }; MAX_CPUS];
+
+static mut STACKS: [KernelStack; MAX_CPUS] =
+    [const { KernelStack([0; KERNEL_STACK_SIZE]) }; MAX_CPUS];
+
+/// Install [`PerCpu`] of core `index` in GS base.
+///
+/// Must be called once per core, before anything else reads its area.
+pub fn init(index: usize) {
+    assert!(index < MAX_CPUS, "core {index} has no per-CPU area");
+
+    // SAFETY: each core only touches its own area and stack.
+    unsafe {
+        let stack = &raw const STACKS[index];
+        let area = &raw mut AREAS[index];
+        (*area).kernel_stack = stack as u64 + KERNEL_STACK_SIZE as u64;
+        (*area).this = area;
+        (*area).index = index;
+        (*area).apic_id = super::apic_id();
+
+        GsBase::write(VirtAddr::from_ptr(area));
+        KernelGsBase::write(VirtAddr::zero());
+    }
+}
+
+/// Current core area.
+///
+/// Only valid with kernel GS base, once [`init`] ran.
+#[inline]
+fn this() -> *mut PerCpu {
+    let this: *mut PerCpu;
+    unsafe {
+        asm!(
+            "mov {}, gs:[{offset}]",
+            out(reg) this,
+            offset = const THIS,
+            options(nostack, preserves_flags, readonly)
+        );
+    }
+    this
+}
+
+/// Logical index of current core.
+#[inline]
+pub fn index() -> usize {
+    unsafe { (*this()).index }
+}
+
+/// LAPIC ID of core `index`, if it is initialized.
+pub fn apic_id_of(index: usize) -> Option<u32> {
+    // SAFETY: the ID is written once, before the core accepts IPIs.
+    let apic_id = unsafe { (*(&raw const AREAS)).get(index)?.apic_id };
+    (apic_id != u32::MAX).then_some(apic_id)
+}
+
+/// Thread running on current core.
+#[inline]
+pub fn current() -> Option<NonNull<Tcb>> {
+    unsafe { (*this()).current }
+}
+
+/// Set thread running on current core.
+#[inline]
+pub fn set_current(tcb: Option<NonNull<Tcb>>) {
+    unsafe { (*this()).current = tcb };
+}
+
+/// Executor of current core, once it is attached.
+#[inline]
+pub fn executor() -> Option<NonNull<Executor>> {
+    unsafe { (*this()).executor }
+}
+
+/// Attach `executor` to current core.
+pub fn set_executor(executor: NonNull<Executor>) {
+    unsafe { (*this()).executor = Some(executor) };
+}
+
+/// Count a timer tick on current core.
+#[inline]
+pub fn count_tick() {
+    unsafe { (*this()).ticks += 1 };
+}
+
+/// Timer ticks counted on current core.
+#[inline]
+pub fn ticks() -> u64 {
+    unsafe { (*this()).ticks }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn boot_core_area() {
+        assert_eq!(index(), 0);
+        assert_eq!(apic_id_of(0), Some(super::super::apic_id()));
+        assert_eq!(apic_id_of(MAX_CPUS), None);
+    }
+}
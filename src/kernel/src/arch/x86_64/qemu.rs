@@ -0,0 +1,23 @@
+use x86_64::instructions::port::Port;
+
+/// I/O port of the `isa-debug-exit` device, must match the runner.
+const DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// Value written to the `isa-debug-exit` device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+/// Exit QEMU with `code`.
+pub fn exit_qemu(code: QemuExitCode) -> ! {
+    unsafe {
+        Port::<u32>::new(DEBUG_EXIT_PORT).write(code as u32);
+    }
+
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
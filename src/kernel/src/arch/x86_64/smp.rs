@@ -0,0 +1,317 @@
+//! Application processors bring-up.
+//!
+//! The bootstrap processor copies a real-mode trampoline in a free page of
+//! conventional memory and wakes each AP with INIT-SIPI-SIPI. The
+//! trampoline enters long mode on temporary page tables, which identity map
+//! low memory and share kernel mappings, then calls [`ap_main`] on the AP
+//! stack.
+//!
+//! APs share the trampoline and its parameters, so they are started one at
+//! a time, each acknowledging once it runs on its own stack. After a
+//! timeout no other AP is started, the late one could still enter the
+//! trampoline and load the stack of the next.
+
+use core::arch::global_asm;
+use core::ptr::addr_of;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering, fence};
+use core::time::Duration;
+
+use bootloader_api::info::{MemoryRegion, MemoryRegionKind};
+use x86_64::registers::control::{Cr3, Cr3Flags};
+use x86_64::structures::paging::{PageTable, PageTableFlags, PhysFrame};
+
+use crate::arch::apic::Apic;
+use crate::arch::constants::apic::ApicValue;
+use crate::arch::tick::Tick;
+use crate::arch::topology::topology;
+use crate::arch::vspace::kernel_paddr;
+use crate::arch::{PhysAddr, VirtAddr};
+use crate::scheduler::{self, MAX_CPUS};
+use crate::vspace::{PAGE_BITS_4K, PAGE_SIZE_4K, phys_to_virt};
+use crate::{APIC, TICKS};
+
+/// AP stack size.
+const AP_STACK_SIZE: usize = 4096 * 8;
+/// Startup vectors from this address point to video memory and ROMs.
+const TRAMPOLINE_END: u64 = 0xA_0000;
+/// Time given to an AP to reach [`ap_main`].
+const AP_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Real mode entry, CS is the trampoline page and EBX keeps its address.
+// Absolute addresses are rebuilt from it since the page is only known at
+// runtime.
+global_asm!(
+    ".pushsection .text.ap_trampoline, \"ax\"",
+    ".code16",
+    ".global ap_trampoline_start",
+    "ap_trampoline_start:",
+    "    jmp .Lap_real",
+    ".balign 8",
+    ".Lap_gdt:",
+    "    .quad 0",
+    "    .quad 0x00cf9a000000ffff", // 0x08, 32-bit code.
+    "    .quad 0x00cf92000000ffff", // 0x10, data.
+    "    .quad 0x00af9a000000ffff", // 0x18, 64-bit code.
+    ".Lap_gdtr:",
+    "    .word .Lap_gdtr - .Lap_gdt - 1",
+    "    .long 0",
+    ".balign 8",
+    ".global ap_trampoline_params",
+    "ap_trampoline_params:",
+    "    .quad 0",
+    "    .quad 0",
+    "    .quad 0",
+    ".set AP_GDT, .Lap_gdt - ap_trampoline_start",
+    ".set AP_GDTR, .Lap_gdtr - ap_trampoline_start",
+    ".set AP_PARAMS, ap_trampoline_params - ap_trampoline_start",
+    ".set AP_PROTECTED, .Lap_protected - ap_trampoline_start",
+    ".set AP_LONG, .Lap_long - ap_trampoline_start",
+    ".Lap_real:",
+    "    cli",
+    "    cld",
+    "    mov ax, cs",
+    "    mov ds, ax",
+    "    mov ss, ax",
+    "    mov sp, 0x1000",
+    "    xor ebx, ebx",
+    "    mov bx, ax",
+    "    shl ebx, 4",
+    "    lea eax, [ebx + AP_GDT]",
+    "    mov dword ptr [AP_GDTR + 2], eax",
+    "    lgdt [AP_GDTR]",
+    "    mov eax, cr0",
+    "    or eax, 1",
+    "    mov cr0, eax",
+    // Far return to 32-bit code.
+    "    mov eax, 0x08",
+    "    push eax",
+    "    lea eax, [ebx + AP_PROTECTED]",
+    "    push eax",
+    "    data32 retf",
+    ".code32",
+    ".Lap_protected:",
+    "    mov ax, 0x10",
+    "    mov ds, ax",
+    "    mov es, ax",
+    "    mov ss, ax",
+    "    lea esp, [ebx + 0x1000]",
+    // PAE, then long mode with NX and write protection.
+    "    mov eax, cr4",
+    "    or eax, 1 << 5",
+    "    mov cr4, eax",
+    "    mov eax, [ebx + AP_PARAMS]",
+    "    mov cr3, eax",
+    "    mov ecx, 0xc0000080",
+    "    rdmsr",
+    "    or eax, (1 << 8) | (1 << 11)",
+    "    wrmsr",
+    "    mov eax, cr0",
+    "    or eax, (1 << 31) | (1 << 16)",
+    "    mov cr0, eax",
+    "    push 0x18",
+    "    lea eax, [ebx + AP_LONG]",
+    "    push eax",
+    "    retf",
+    ".code64",
+    ".Lap_long:",
+    "    mov ebx, ebx",
+    "    mov rsp, [rbx + AP_PARAMS + 8]",
+    "    call qword ptr [rbx + AP_PARAMS + 16]",
+    "    ud2",
+    ".global ap_trampoline_end",
+    "ap_trampoline_end:",
+    ".popsection",
+);
+
+unsafe extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_params: u8;
+    static ap_trampoline_end: u8;
+}
+
+/// Trampoline parameters, at `ap_trampoline_params`.
+#[repr(C)]
+struct TrampolineParams {
+    /// Temporary PML4, below 4 GiB.
+    cr3: u64,
+    /// Top of the AP stack.
+    stack: u64,
+    /// Long mode entry point.
+    entry: u64,
+}
+
+#[repr(C, align(16))]
+struct ApStack([u8; AP_STACK_SIZE]);
+
+static mut AP_STACKS: [ApStack; MAX_CPUS] =
+    [const { ApStack([0; AP_STACK_SIZE]) }; MAX_CPUS];
+
+/// Trampoline PML4, PDPT and PD.
+static mut TABLES: [PageTable; 3] = [const { PageTable::new() }; 3];
+
+/// Kernel PML4, loaded by APs once in long mode.
+static KERNEL_CR3: AtomicU64 = AtomicU64::new(0);
+
+/// Set by the last started AP, once it left the trampoline.
+static AP_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// First usable page for the trampoline.
+fn trampoline_page(regions: &[MemoryRegion]) -> Option<u64> {
+    regions
+        .iter()
+        .filter(|region| region.kind == MemoryRegionKind::Usable)
+        .map(|region| {
+            // Page 0 holds the real mode interrupt vector table.
+            let start = region.start.max(PAGE_SIZE_4K as u64);
+            let start = alignup!(start, PAGE_BITS_4K);
+            (start, region.end.min(TRAMPOLINE_END))
+        })
+        .find(|&(start, end)| start + PAGE_SIZE_4K as u64 <= end)
+        .map(|(start, _)| start)
+}
+
+fn table_paddr(table: &PageTable) -> PhysAddr {
+    kernel_paddr(VirtAddr::from_ptr(table))
+        .expect("trampoline page table not mapped")
+}
+
+/// Build the trampoline page tables and return the PML4 address.
+///
+/// # Safety
+/// APs must not be running on the tables.
+unsafe fn init_tables() -> u64 {
+    let (kernel, _) = Cr3::read();
+    let [pml4, pdpt, pd] = &mut *(&raw mut TABLES);
+
+    *pml4 =
+        (*phys_to_virt(kernel.start_address()).as_ptr::<PageTable>()).clone();
+
+    // Identity map the first 2 MiB, where the trampoline runs.
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    pd[0].set_addr(PhysAddr::zero(), flags | PageTableFlags::HUGE_PAGE);
+    pdpt[0].set_addr(table_paddr(pd), flags);
+    pml4[0].set_addr(table_paddr(pdpt), flags);
+
+    let paddr = table_paddr(pml4).as_u64();
+    assert!(paddr < 1 << 32, "trampoline PML4 above 4 GiB");
+    paddr
+}
+
+/// Start every enabled processor but the boot one.
+///
+/// Must be called once, APs program their timer with the calibrated
+/// period.
+pub fn init(regions: &[MemoryRegion]) {
+    let cpus = topology().cpus();
+    if cpus.len() < 2 {
+        return;
+    }
+
+    // Identity mapped trampoline code must not be in the way.
+    let entry = VirtAddr::new(ap_main as usize as u64);
+    assert_ne!(u16::from(entry.p4_index()), 0, "kernel in first PML4 entry");
+
+    let Some(page) = trampoline_page(regions) else {
+        log::warn!("no conventional memory left, APs not started");
+        return;
+    };
+
+    while super::tick::period().is_none() {
+        super::halt();
+    }
+    let tick = TICKS.lock().clone();
+    let apic = *APIC.lock();
+
+    let (kernel, _) = Cr3::read();
+    KERNEL_CR3.store(kernel.start_address().as_u64(), Ordering::Relaxed);
+
+    // SAFETY: the page is usable memory left out of untypeds, no AP runs.
+    let params = unsafe {
+        let start = addr_of!(ap_trampoline_start);
+        let size = addr_of!(ap_trampoline_end) as usize - start as usize;
+        let trampoline = phys_to_virt(PhysAddr::new(page)).as_mut_ptr::<u8>();
+        trampoline.copy_from_nonoverlapping(start, size);
+
+        let offset = addr_of!(ap_trampoline_params) as usize - start as usize;
+        let params = &mut *trampoline.add(offset).cast::<TrampolineParams>();
+        params.cr3 = init_tables();
+        params.entry = entry.as_u64();
+        params
+    };
+
+    // The boot processor is logical processor 0.
+    for (index, cpu) in cpus.iter().enumerate().skip(1) {
+        let apic_id = cpu.apic_id;
+        if apic_id > u8::MAX as u32 {
+            log::warn!("core {index} cannot be started");
+            continue;
+        }
+
+        params.stack = unsafe { addr_of!(AP_STACKS[index]) } as u64 +
+            AP_STACK_SIZE as u64;
+        fence(Ordering::SeqCst);
+
+        if !start_ap(&apic, &tick, apic_id, page) {
+            log::warn!("core {index} did not start, next cores left off");
+            break;
+        }
+    }
+}
+
+/// Send INIT-SIPI-SIPI to `apic_id`, the trampoline being at `page`.
+fn start_ap(apic: &Apic, tick: &Tick, apic_id: u32, page: u64) -> bool {
+    AP_STARTED.store(false, Ordering::Release);
+    let vector = (page >> PAGE_BITS_4K) as u32;
+
+    apic.send_ipi(apic_id, ApicValue::IcrInit as u32);
+    tick.delay(Duration::from_millis(10));
+
+    // A running AP ignores the second SIPI.
+    for _ in 0..2 {
+        apic.send_ipi(apic_id, ApicValue::IcrStartup as u32 | vector);
+        tick.delay(Duration::from_micros(200));
+    }
+
+    let step = Duration::from_millis(1);
+    let mut waited = Duration::ZERO;
+    while !AP_STARTED.load(Ordering::Acquire) {
+        if waited >= AP_TIMEOUT {
+            return false;
+        }
+        tick.delay(step);
+        waited += step;
+    }
+    true
+}
+
+/// Application processor entry, called by the trampoline.
+extern "C" fn ap_main() -> ! {
+    // Running on its own stack, the trampoline is free for the next AP.
+    AP_STARTED.store(true, Ordering::Release);
+
+    // SAFETY: kernel mappings are the same in trampoline tables.
+    unsafe {
+        let pml4 = PhysAddr::new(KERNEL_CR3.load(Ordering::Relaxed));
+        Cr3::write(PhysFrame::containing_address(pml4), Cr3Flags::empty());
+    }
+    super::vspace::init_pcid();
+
+    let index = topology()
+        .index_of(super::apic_id())
+        .expect("started core not in topology");
+    super::percpu::init(index);
+    super::interrupts::load();
+
+    let apic = *APIC.lock();
+    apic.init_ap();
+    super::syscall::init_syscall();
+
+    let period = super::tick::period().expect("timer not calibrated");
+    apic.init_counter(true, period);
+
+    log::info!("core {index} started");
+
+    scheduler::init_core();
+    super::ipi::init();
+    unsafe { scheduler::executor() }.run()
+}
@@ -6,16 +6,29 @@ use x86_64::registers::model_specific::{
 };
 use x86_64::registers::rflags::RFlags;
 
-use crate::arch::interrupts::gdt::GDT;
+use crate::arch::interrupts::gdt::selectors;
+use crate::arch::percpu::{KERNEL_STACK, USER_RSP};
+use crate::arch::trapframe::TrapFrame;
+use crate::error::SysError;
+use crate::objects::tcb::{Tcb, ThreadState};
+use crate::scheduler;
 
 /// Set method handler for syscalls.
 pub fn init_syscall() {
-    let user_cs = GDT.1.user_code_selector.0;
-    let kernel_cs = GDT.1.code_selector.0;
+    let selectors = selectors();
+    log::debug!(
+        "selectors are {:?} (user) and {:?} (kernel)",
+        selectors.user_code_selector,
+        selectors.code_selector,
+    );
 
-    log::debug!("selectors are {user_cs} (user) and {kernel_cs} (kernel)");
-
-    unsafe { Star::write_raw(user_cs, kernel_cs) }
+    Star::write(
+        selectors.user_code_selector,
+        selectors.user_data_selector,
+        selectors.code_selector,
+        selectors.data_selector,
+    )
+    .expect("GDT layout does not match syscall and sysret");
 
     let addr = syscall_stub as *const ();
     let handler = VirtAddr::new(addr as u64);
@@ -31,69 +44,108 @@ pub fn init_syscall() {
     unsafe { Efer::write(efer) };
 }
 
-/// Syscall registers.
-#[derive(Debug, Default, Clone, Copy)]
-#[repr(C)]
-pub struct Regs {
-    /// Syscall number.
-    pub rax: u64,
-    // Destination index.
-    pub rdi: u64,
-    // Source index.
-    pub rsi: u64,
-    /// Data register.
-    pub rdx: u64,
-    pub r8: u64,
-    pub r9: u64,
-    pub r10: u64,
-    /// RFLAGS (CPU flags).
-    pub r11: u64,
-    pub rcx: u64,
-}
+/// Index of RAX in [`TrapFrame`] registers.
+const RAX: usize = 0;
+/// Index of RDX in [`TrapFrame`] registers.
+const RDX: usize = 3;
 
 #[unsafe(naked)]
 extern "C" fn syscall_stub() {
     naked_asm!(
         "swapgs", // switch GS -> kernel
-        // Save fallbakcs.
-        "push rcx", // user RIP.
-        "push r11", // user RFLAGS.
-        // Build register on stack.
+        // Switch to kernel stack.
+        "mov gs:[{user_rsp}], rsp",
+        "mov rsp, gs:[{kernel_stack}]",
+        // Build a trap frame: SS, user RSP, user RFLAGS, CS, user RIP and
+        // error code. Segments are kept from thread context.
+        "push 0",
+        "push qword ptr gs:[{user_rsp}]",
+        "push r11",
+        "push 0",
+        "push rcx",
+        "push 0",
+        "push r15",
+        "push r14",
+        "push r13",
+        "push r12",
+        "push r11",
         "push r10",
         "push r9",
         "push r8",
-        "push rdx",
-        "push rsi",
+        "push rbp",
         "push rdi",
+        "push rsi",
+        "push rdx",
+        "push rcx",
+        "push rbx",
         "push rax",
         "mov rdi, rsp",
         "call syscall_entry",
-        // Restore stack.
+        // Restore registers.
         "pop rax",
-        "pop rdi",
-        "pop rsi",
+        "pop rbx",
+        "pop rcx",
         "pop rdx",
+        "pop rsi",
+        "pop rdi",
+        "pop rbp",
         "pop r8",
         "pop r9",
         "pop r10",
         "pop r11",
-        "pop rcx",
+        "pop r12",
+        "pop r13",
+        "pop r14",
+        "pop r15",
+        "add rsp, 8", // error code.
+        "pop rcx",    // user RIP.
+        "add rsp, 8", // CS.
+        "pop r11",    // user RFLAGS.
+        "pop rsp",    // back to user stack.
         "swapgs",
         "sysretq",
+        user_rsp = const USER_RSP,
+        kernel_stack = const KERNEL_STACK,
     )
 }
 
 #[unsafe(no_mangle)]
-extern "C" fn syscall_entry(registers: &mut Regs) -> u64 {
-    let args = [
-        registers.rdi,
-        registers.rsi,
-        registers.rdx,
-        registers.r10,
-        registers.r8,
-        registers.r9,
-    ];
-    let _ret = crate::syscall::handler(registers.rax, args);
+extern "C" fn syscall_entry(frame: &mut TrapFrame) {
+    let _lock = scheduler::lock::acquire();
+    // SAFETY: syscalls run with interrupts disabled.
+    let executor = unsafe { scheduler::executor() };
+    let Some(current) = executor.current() else {
+        log::warn!("syscall without current thread");
+        return;
+    };
+
+    // SAFETY: current thread is valid while it runs.
+    let tcb = unsafe { &mut *current.as_ptr() };
+    tcb.context.registers = frame.registers;
+    tcb.context.rip = frame.rip;
+    tcb.context.rflags = frame.rflags;
+    tcb.context.rsp = frame.rsp;
+
+    let args = [Tcb::MR1, Tcb::MR2, RDX, Tcb::MR3, Tcb::MR4, Tcb::MR5]
+        .map(|idx| frame.registers[idx] as u64);
+    let ret =
+        crate::syscall::handler(frame.registers[RAX] as u64, args, current);
+    tcb.context.registers[RAX] = ret.err().unwrap_or(SysError::None).as_code();
+
+    // Intel raises #GP in ring 0 on `sysret` to a non-canonical RIP.
+    let rip = tcb.context.rip;
+    if VirtAddr::try_new(rip as u64).is_err() {
+        log::warn!("thread stopped on non-canonical return to {rip:#x}");
+        unsafe { executor.block_current(ThreadState::Inactive) }
+    }
 
-    1
+    // Thread resumes here unless it is blocked or preempted.
+    unsafe {
+        match tcb.state {
+            ThreadState::Running => executor.reschedule(),
+            state => executor.block_current(state),
+        }
+    }
+    frame.registers = tcb.context.registers;
+    frame.rip = rip;
 }
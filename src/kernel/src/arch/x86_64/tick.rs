@@ -1,24 +1,34 @@
+use core::sync::atomic::{AtomicU32, Ordering};
 use core::time::Duration;
 
+use crate::arch::acpi::madt;
 use crate::arch::apic::Apic;
+use crate::arch::constants::interrupts::IdtIndex;
 use crate::arch::pit::{Mode, Pit};
+use crate::arch::topology::topology;
 
 const DEFAULT_TICKS_HZ: f32 = 100.0; // Default to 10ms.
 const CALIBRATION_SAMPLES: usize = 10;
+/// ISA IRQ of the PIT.
+const PIT_IRQ: u8 = 0;
+
+/// LAPIC timer cycles per tick, 0 until calibrated.
+static PERIOD: AtomicU32 = AtomicU32::new(0);
+
+/// LAPIC timer cycles per tick, once calibrated.
+///
+/// Unlike [`Tick::period`], it does not need the tick manager lock.
+pub fn period() -> Option<u32> {
+    let period = PERIOD.load(Ordering::Acquire);
+    (period != 0).then_some(period)
+}
 
 fn set_ioapic_pit_interrupt(apic: Apic) {
-    let gsi = 2;
-    let vector = 0x20; // IDT handler index for timer.
-    let dest_apic_id = 0; // CPU0.
-
-    let low_index = 0x10 + gsi * 2;
-    let high_index = low_index + 1;
+    let gsi = madt().isa_gsi(PIT_IRQ);
+    let bsp = topology().bsp() as u8;
 
-    // Configure targeted CPU.
-    apic.ioapic_write(high_index, (dest_apic_id as u32) << 24);
-
-    let low_value = vector as u32;
-    apic.ioapic_write(low_index, low_value);
+    apic.ioapic_redirect(gsi, IdtIndex::Timer as u8, bsp, false);
+    crate::objects::irq::reserve(gsi as usize);
 }
 
 /// Tick handler.
@@ -26,9 +36,10 @@ fn set_ioapic_pit_interrupt(apic: Apic) {
 pub struct Tick {
     apic: Apic,
     is_calibration: bool,
-    ticks: u64,
     duration: Duration,
     lapic_counter: u32,
+    /// LAPIC timer cycles per tick, 0 until calibrated.
+    period: u32,
     calibration: [u32; CALIBRATION_SAMPLES],
     calibration_idx: usize,
 }
@@ -39,27 +50,53 @@ impl Tick {
         Self {
             apic: Apic::new(),
             is_calibration: true,
-            ticks: 0,
             duration: Duration::from_millis(50),
             lapic_counter: 0,
+            period: 0,
             calibration: [0; CALIBRATION_SAMPLES],
             calibration_idx: 0,
         }
     }
 
-    /// Handle each ticks from interrupts.
-    pub fn tick_handler(&mut self) {
+    /// Handle a timer interrupt, cores count ticks on their own once
+    /// [`period`] is known.
+    ///
+    /// Return whether current thread should be preempted, which is left to
+    /// the caller once the tick is unlocked.
+    pub fn tick_handler(&mut self) -> bool {
         if self.is_calibration {
             self.end_calibration();
+            false
         } else {
-            self.ticks += 1;
-            unsafe {
-                crate::scheduler::SCHEDULER
-                    .get()
-                    .expect("scheduler not initialized")
-                    .get_mut()
-                    .preempt()
+            true
+        }
+    }
+
+    /// LAPIC timer cycles per tick, once calibrated.
+    pub fn period(&self) -> Option<u32> {
+        (self.period != 0).then_some(self.period)
+    }
+
+    /// Spin for `duration` on this core LAPIC timer.
+    ///
+    /// Timer must be calibrated.
+    pub fn delay(&self, duration: Duration) {
+        let period = self.period().expect("timer not calibrated") as u64;
+        let tick_micros = (1_000_000.0 / DEFAULT_TICKS_HZ) as u64;
+
+        let mut remaining = duration.as_micros() as u64 * period / tick_micros;
+        let mut last = self.apic.read_counter() as u64;
+        while remaining > 0 {
+            core::hint::spin_loop();
+            let now = self.apic.read_counter() as u64;
+            // Periodic counter reloads once it reaches 0.
+            let elapsed = if now <= last {
+                last - now
+            } else {
+                last + period - now
             };
+            remaining = remaining.saturating_sub(elapsed);
+            last = now;
         }
     }
 
@@ -112,7 +149,9 @@ impl Tick {
             );
 
             self.apic.init_counter(true, cycles_mean);
+            self.period = cycles_mean;
             self.is_calibration = false;
+            PERIOD.store(cycles_mean, Ordering::Release);
         } else {
             self.init_counters();
         }
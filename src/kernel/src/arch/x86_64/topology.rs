@@ -0,0 +1,233 @@
+//! Processor topology.
+//!
+//! Enabled processors of the MADT get a dense logical index, the boot
+//! processor being 0. Their APIC ID is split in package, core and thread
+//! following the bit fields given by CPUID leaf 0x1F, or 0xB on older
+//! processors.
+
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+
+use heapless::Vec;
+use spin::Once;
+
+use crate::arch::acpi::MadtInfo;
+use crate::scheduler::MAX_CPUS;
+
+/// Extended topology level type ending enumeration.
+const LEVEL_INVALID: u32 = 0;
+/// Extended topology level type of threads within a core.
+const LEVEL_SMT: u32 = 1;
+
+static TOPOLOGY: Once<Topology> = Once::new();
+
+/// APIC ID bit fields, shared by every package.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shifts {
+    /// APIC ID bits of the thread within its core.
+    pub thread: u32,
+    /// APIC ID bits below the package ID.
+    pub package: u32,
+}
+
+impl Shifts {
+    /// Read bit fields from CPUID of current processor.
+    fn read() -> Self {
+        let max_leaf = __cpuid(0).eax;
+        [0x1F, 0xB]
+            .into_iter()
+            .filter(|&leaf| max_leaf >= leaf)
+            .find_map(Self::extended)
+            .unwrap_or_else(Self::legacy)
+    }
+
+    /// Enumerate levels of extended topology `leaf`.
+    fn extended(leaf: u32) -> Option<Self> {
+        let mut shifts = None::<Self>;
+        for subleaf in 0.. {
+            let cpuid = __cpuid_count(leaf, subleaf);
+            let level = (cpuid.ecx >> 8) & 0xff;
+            if level == LEVEL_INVALID || cpuid.ebx == 0 {
+                break;
+            }
+
+            let shift = cpuid.eax & 0x1f;
+            let shifts = shifts.get_or_insert(Self {
+                thread: 0,
+                package: 0,
+            });
+            if level == LEVEL_SMT {
+                shifts.thread = shift;
+            }
+            // Levels come in order, the last one ends below the package.
+            shifts.package = shift;
+        }
+        shifts
+    }
+
+    /// Bit fields from the logical processor count of leaf 1, without
+    /// telling threads and cores apart.
+    fn legacy() -> Self {
+        let cpuid = __cpuid(1);
+        let htt = cpuid.edx & (1 << 28) != 0;
+        let count = if htt { (cpuid.ebx >> 16) & 0xff } else { 1 };
+        Self {
+            thread: 0,
+            package: count.max(1).next_power_of_two().ilog2(),
+        }
+    }
+}
+
+/// Enabled logical processor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cpu {
+    pub apic_id: u32,
+    pub package: u32,
+    pub core: u32,
+    pub thread: u32,
+}
+
+impl Cpu {
+    /// Split `apic_id` following `shifts`.
+    pub const fn new(apic_id: u32, shifts: Shifts) -> Self {
+        let core_bits = shifts.package - shifts.thread;
+        Self {
+            apic_id,
+            package: apic_id.checked_shr(shifts.package).unwrap_or(0),
+            core: (apic_id >> shifts.thread) & ((1 << core_bits) - 1),
+            thread: apic_id & ((1 << shifts.thread) - 1),
+        }
+    }
+}
+
+/// Enabled logical processors, indexed by logical index.
+#[derive(Debug, Clone)]
+pub struct Topology {
+    cpus: Vec<Cpu, MAX_CPUS>,
+}
+
+impl Topology {
+    /// Index enabled processors of `madt`, `bsp` first.
+    pub fn new(madt: &MadtInfo, bsp: u32, shifts: Shifts) -> Self {
+        let mut cpus = Vec::new();
+        let _ = cpus.push(Cpu::new(bsp, shifts));
+
+        let aps = madt
+            .processors
+            .iter()
+            .filter(|cpu| cpu.enabled && cpu.apic_id != bsp);
+        for processor in aps {
+            if cpus.push(Cpu::new(processor.apic_id, shifts)).is_err() {
+                log::warn!("more than {MAX_CPUS} CPUs, {processor:?} ignored");
+            }
+        }
+        Self { cpus }
+    }
+
+    /// Processors, in logical index order.
+    pub fn cpus(&self) -> &[Cpu] {
+        &self.cpus
+    }
+
+    /// Logical index of `apic_id`.
+    pub fn index_of(&self, apic_id: u32) -> Option<usize> {
+        self.cpus.iter().position(|cpu| cpu.apic_id == apic_id)
+    }
+
+    /// APIC ID of the boot processor, which serves device interrupts.
+    pub fn bsp(&self) -> u32 {
+        self.cpus[0].apic_id
+    }
+
+    /// APIC ID of logical processor `index`.
+    pub fn apic_id(&self, index: usize) -> Option<u32> {
+        self.cpus.get(index).map(|cpu| cpu.apic_id)
+    }
+
+    /// Number of distinct packages.
+    pub fn packages(&self) -> u32 {
+        self.count(|a, b| a.package == b.package)
+    }
+
+    /// Number of distinct cores, in all packages.
+    pub fn cores(&self) -> u32 {
+        self.count(|a, b| a.package == b.package && a.core == b.core)
+    }
+
+    /// Number of logical processors.
+    pub fn threads(&self) -> u32 {
+        self.cpus.len() as u32
+    }
+
+    /// Number of processors not `same` as any previous one.
+    fn count(&self, same: impl Fn(&Cpu, &Cpu) -> bool) -> u32 {
+        self.cpus
+            .iter()
+            .enumerate()
+            .filter(|&(i, cpu)| !self.cpus[..i].iter().any(|p| same(p, cpu)))
+            .count() as u32
+    }
+}
+
+/// Discover topology from `madt`, on the boot processor.
+///
+/// Must be called once, before [`topology`].
+pub fn init(madt: &MadtInfo) -> &'static Topology {
+    TOPOLOGY.call_once(|| {
+        let topology = Topology::new(madt, super::apic_id(), Shifts::read());
+        log::info!(
+            "topology: {} packages, {} cores, {} threads",
+            topology.packages(),
+            topology.cores(),
+            topology.threads(),
+        );
+        topology
+    })
+}
+
+/// Discovered topology.
+pub fn topology() -> &'static Topology {
+    TOPOLOGY.get().expect("topology not discovered")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::acpi::Processor;
+
+    #[test_case]
+    fn enabled_cpus_are_indexed_from_bsp() {
+        let shifts = Shifts {
+            thread: 1,
+            package: 4,
+        };
+        let mut madt = MadtInfo {
+            lapic_paddr: 0xfee0_0000,
+            ioapics: Vec::new(),
+            overrides: Vec::new(),
+            processors: Vec::new(),
+        };
+        for (apic_id, enabled) in
+            [(0, true), (1, true), (2, false), (0x13, true)]
+        {
+            let processor = Processor {
+                uid: apic_id,
+                apic_id,
+                enabled,
+            };
+            madt.processors.push(processor).unwrap();
+        }
+
+        let topology = Topology::new(&madt, 1, shifts);
+        assert_eq!(topology.bsp(), 1);
+        assert_eq!(topology.index_of(1), Some(0));
+        assert_eq!(topology.index_of(0x13), Some(2));
+        assert_eq!(topology.index_of(2), None);
+        assert_eq!(topology.apic_id(1), Some(0));
+
+        let cpu = topology.cpus()[2];
+        assert_eq!((cpu.package, cpu.core, cpu.thread), (1, 1, 1));
+        assert_eq!(topology.packages(), 2);
+        assert_eq!(topology.cores(), 2);
+        assert_eq!(topology.threads(), 3);
+    }
+}
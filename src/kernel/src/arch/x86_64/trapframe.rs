@@ -1,7 +1,9 @@
-use core::arch::asm;
+use core::arch::{asm, naked_asm};
 
 use x86_64::registers::rflags::RFlags;
 
+use crate::arch::interrupts::gdt::selectors;
+
 const REGISTERS_SIZE: usize = 15;
 
 #[derive(Debug, Clone, Copy)]
@@ -27,18 +29,27 @@ impl TrapFrame {
         unsafe { core::mem::zeroed() }
     }
 
+    /// Create a [`TrapFrame`] entering user mode at `rip`, on stack `rsp`,
+    /// with interrupts enabled.
+    pub fn user(rip: usize, rsp: usize) -> Self {
+        let selectors = selectors();
+        let mut frame = Self::new();
+        frame.rip = rip;
+        frame.rsp = rsp;
+        frame.cs = selectors.user_code_selector.0 as usize;
+        frame.ss = selectors.user_data_selector.0 as usize;
+        frame.rflags = RFlags::INTERRUPT_FLAG;
+        frame
+    }
+
     /// Restore userland context after context switching.
     pub fn restore(&mut self) -> ! {
         unsafe {
             asm!(
                 "mov rsp, {ptr}",
-                "pop rax", "pop rbx", "pop rcx", "pop rdx",
-                "pop rsi", "pop rdi", "pop rbp", "pop r8",
-                "pop r9", "pop r10", "pop r11", "pop r12",
-                "pop r13", "pop r14", "pop r15",
-                "add rsp, 8",
-                "iretq",
+                "jmp {exit}",
                 ptr = in(reg) self,
+                exit = sym trap_exit,
                 options(noreturn)
             );
         }
@@ -62,3 +73,34 @@ impl TrapFrame {
         self.registers[idx] = mr;
     }
 }
+
+/// Return to the context of the [`TrapFrame`] at RSP.
+///
+/// Common exit of interrupts and context switches.
+#[unsafe(naked)]
+pub extern "C" fn trap_exit() {
+    naked_asm!(
+        "pop rax",
+        "pop rbx",
+        "pop rcx",
+        "pop rdx",
+        "pop rsi",
+        "pop rdi",
+        "pop rbp",
+        "pop r8",
+        "pop r9",
+        "pop r10",
+        "pop r11",
+        "pop r12",
+        "pop r13",
+        "pop r14",
+        "pop r15",
+        "add rsp, 8",
+        // Back to user GS base, see `percpu`.
+        "test qword ptr [rsp + 8], 3",
+        "jz 2f",
+        "swapgs",
+        "2:",
+        "iretq",
+    )
+}
@@ -1,5 +1,198 @@
 //! Virtual address space management for x86-64.
+//!
+//! With PCIDs, TLB entries are tagged with the ASID of their VSpace and
+//! survive context switches, unmapping a page then invalidates it for its
+//! ASID only. ASID 0 and those above the 12 bits PCID range share PCID 0,
+//! which is flushed whenever a VSpace using it is switched to.
 
 pub mod entry;
 pub mod level;
 pub mod tlb;
+
+use core::arch::asm;
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Once;
+use x86_64::instructions::tlb::Pcid;
+use x86_64::registers::control::{Cr3, Cr4, Cr4Flags};
+use x86_64::structures::paging::PhysFrame;
+
+use self::entry::{PageTableEntry, Pml4e};
+use self::level::Pml4;
+use crate::PHYS_MEM_OFFSET;
+use crate::arch::{PhysAddr, VirtAddr};
+use crate::error::WalkResult;
+use crate::objects::CapRights;
+use crate::objects::cnode::CNodeEntry;
+use crate::objects::vspace::{Asid, VSpaceCap};
+use crate::vspace::Table;
+
+/// First PML4 index of the kernel half, shared by every VSpace.
+pub const KERNEL_PML4_START: usize = 256;
+/// Number of PML4 entries.
+const PML4_ENTRIES: usize = 512;
+
+/// CR3 bit keeping TLB entries of the loaded PCID.
+const CR3_NOFLUSH: u64 = 1 << 63;
+
+/// Whether cores tag TLB entries with PCIDs, see [`init_pcid`].
+static PCID: AtomicBool = AtomicBool::new(false);
+
+/// Kernel half of the boot PML4, copied in every VSpace.
+static KERNEL_HALF: Once<[Pml4e; PML4_ENTRIES - KERNEL_PML4_START]> =
+    Once::new();
+
+/// Translate a kernel virtual address with the active page tables.
+pub fn kernel_paddr(vaddr: VirtAddr) -> Option<PhysAddr> {
+    let (pml4, _) = Cr3::read();
+    let entry = CNodeEntry::new();
+    entry.set(VSpaceCap::mint(
+        pml4.start_address().as_u64() as usize,
+        0,
+        CapRights::READ,
+    ));
+    let vspace = VSpaceCap::try_from(&entry).ok()?;
+
+    match unsafe { vspace.walk::<PHYS_MEM_OFFSET>(vaddr) } {
+        Ok(WalkResult::MappedPage { paddr, size, .. }) => {
+            let offset = vaddr.as_u64() as usize & size.align_mask();
+            Some(PhysAddr::new((paddr + offset) as u64))
+        },
+        _ => None,
+    }
+}
+
+/// Record the kernel half of the active PML4, see [`copy_kernel_half`].
+///
+/// Must be called on the boot core once kernel mappings are set up, before
+/// any VSpace is created. Kernel mappings added later in these entries are
+/// shared, new PML4 entries are not.
+pub fn init_kernel_half() {
+    let kernel = init_kernel_half as *const () as u64;
+    assert!(
+        kernel >> 47 != 0,
+        "kernel is linked in the lower half, at {kernel:#x}"
+    );
+
+    let (frame, _) = Cr3::read();
+    let root = frame.start_address();
+    // SAFETY: the active PML4 is reachable in the physical memory window.
+    let pml4 = unsafe { Table::<Pml4>::from_paddr::<PHYS_MEM_OFFSET>(root) };
+
+    KERNEL_HALF.call_once(|| {
+        core::array::from_fn(|i| {
+            let entry = pml4[KERNEL_PML4_START + i];
+            // A recursive entry would point at the boot PML4.
+            if entry.is_present() && entry.paddr() == root {
+                Pml4e::invalid()
+            } else {
+                entry
+            }
+        })
+    });
+}
+
+/// Copy kernel mappings in the upper half of `pml4`.
+///
+/// # Safety
+/// `pml4` must be a PML4 owned by the caller.
+pub unsafe fn copy_kernel_half(pml4: PhysAddr) {
+    let kernel = KERNEL_HALF.get().expect("kernel half not recorded");
+    let pml4 = Table::<Pml4>::from_paddr::<PHYS_MEM_OFFSET>(pml4);
+    for (i, entry) in kernel.iter().enumerate() {
+        pml4[KERNEL_PML4_START + i] = *entry;
+    }
+}
+
+/// Enable PCIDs on current core, if the CPU supports them and `invpcid`.
+///
+/// Must be called once per core, with a CR3 holding no flags.
+pub fn init_pcid() {
+    let pcid = __cpuid(1).ecx & (1 << 17) != 0;
+    let invpcid =
+        __cpuid(0).eax >= 7 && __cpuid_count(7, 0).ebx & (1 << 10) != 0;
+    let enabled = pcid && invpcid && Cr3::read().1.is_empty();
+    if enabled {
+        unsafe { Cr4::update(|flags| flags.insert(Cr4Flags::PCID)) };
+    }
+    PCID.store(enabled, Ordering::Relaxed);
+}
+
+/// PCID tagging TLB entries of `asid`, if it gets its own one.
+fn pcid(asid: Asid) -> Option<Pcid> {
+    if asid == 0 || !PCID.load(Ordering::Relaxed) {
+        return None;
+    }
+    Pcid::new(asid).ok()
+}
+
+/// Switch to the address space rooted at `root`, of `asid`.
+///
+/// Without a PCID of its own, the TLB is flushed unless `root` is active.
+///
+/// # Safety
+/// `root` must map the kernel, as the active PML4 does.
+pub unsafe fn activate(root: PhysAddr, asid: Asid) {
+    if !PCID.load(Ordering::Relaxed) {
+        let (active, flags) = Cr3::read();
+        if active.start_address() != root {
+            Cr3::write(PhysFrame::containing_address(root), flags);
+        }
+        return;
+    }
+
+    let (active, active_pcid) = Cr3::read_pcid();
+    let (pcid, noflush) = match pcid(asid) {
+        Some(pcid) => (pcid, CR3_NOFLUSH),
+        None => (Pcid::new(0).unwrap(), 0),
+    };
+    if active.start_address() != root || active_pcid != pcid {
+        let cr3 = root.as_u64() | pcid.value() as u64 | noflush;
+        asm!("mov cr3, {}", in(reg) cr3, options(nostack, preserves_flags));
+    }
+}
+
+/// Invalidate `vaddr` of `asid` on current core.
+pub fn flush_asid_page(asid: Asid, vaddr: VirtAddr) {
+    match pcid(asid) {
+        Some(pcid) => tlb::flush_page_pcid(pcid.value(), vaddr),
+        None => tlb::flush_page(vaddr),
+    }
+}
+
+/// Invalidate every TLB entry of `asid` on current core.
+///
+/// Entries of ASIDs without a PCID of their own are flushed on switch.
+pub fn flush_asid(asid: Asid) {
+    if let Some(pcid) = pcid(asid) {
+        tlb::invalidate_pcid(pcid.value());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::VSpaceError;
+    use crate::testing::Page;
+
+    #[test_case]
+    fn vspace_shares_kernel_half() {
+        let page = Page::new();
+        let vaddr = VirtAddr::from_ptr(&page);
+        let paddr = page.paddr();
+        unsafe { copy_kernel_half(paddr) };
+
+        let entry = CNodeEntry::new();
+        entry.set(VSpaceCap::mint(
+            paddr.as_u64() as usize,
+            1,
+            CapRights::all(),
+        ));
+        let vspace = VSpaceCap::try_from(&entry).unwrap();
+        let walk = unsafe { vspace.walk::<PHYS_MEM_OFFSET>(vaddr) };
+        assert!(matches!(walk, Ok(WalkResult::MappedPage { .. })));
+        let unmap = unsafe { vspace.unmap::<PHYS_MEM_OFFSET>(vaddr) };
+        assert_eq!(unmap.err(), Some(VSpaceError::InvalidVAddr));
+    }
+}
@@ -16,6 +16,31 @@ pub fn flush_page(vaddr: VirtAddr) {
     }
 }
 
+/// Flush `vaddr` from the TLB entries tagged with `pcid`, whether it is
+/// active or not.
+#[inline]
+pub fn flush_page_pcid(pcid: u16, vaddr: VirtAddr) {
+    #[repr(C)]
+    struct InvpcidDesc {
+        pcid: u64,
+        addr: u64,
+    }
+
+    let desc = InvpcidDesc {
+        pcid: pcid as u64,
+        addr: vaddr.as_u64(),
+    };
+
+    unsafe {
+        asm!(
+            "invpcid {0}, [{1}]",
+            in(reg) 0u64, // Type 0: Individual address.
+            in(reg) &desc,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
 /// Flush the entire TLB by reloading CR3.
 #[inline]
 pub fn flush_all() {
@@ -46,7 +71,7 @@ pub fn invalidate_pcid(pcid: u16) {
     unsafe {
         asm!(
             "invpcid {0}, [{1}]",
-            in(reg) 0u64, // Type 0: Individual address.
+            in(reg) 1u64, // Type 1: Single context.
             in(reg) &desc,
             options(nostack, preserves_flags)
         );
@@ -0,0 +1,122 @@
+//! Boot information page handed to the root task.
+//!
+//! The page is mapped read-only at [`BOOT_INFO_VADDR`] and its address is
+//! passed to the root task entry point in RDI.
+
+use core::ops::Range;
+
+use crate::boot::{BootCNode, ROOT_CNODE_RADIX, ROOT_CNODE_SLOTS, RootSlot};
+use crate::objects::CapRef;
+use crate::objects::untyped::UntypedObj;
+use crate::vspace::PAGE_SIZE_4K;
+
+/// Root task virtual address of [`BootInfo`].
+pub const BOOT_INFO_VADDR: u64 = 0x7fff_ffff_1000;
+
+/// Root task virtual address of its IPC buffer.
+pub const IPC_BUFFER_VADDR: u64 = 0x7fff_ffff_2000;
+
+/// Maximum number of untyped descriptors in [`BootInfo`].
+pub const MAX_BOOTINFO_UNTYPED: usize = 230;
+
+/// Range `[start, end)` of root CNode slots.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct SlotRegion {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl From<Range<usize>> for SlotRegion {
+    fn from(range: Range<usize>) -> Self {
+        Self {
+            start: range.start,
+            end: range.end,
+        }
+    }
+}
+
+/// Untyped capability description.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct UntypedDesc {
+    /// Physical base address.
+    pub paddr: u64,
+    /// Size in bits.
+    pub size_bits: u8,
+    /// Device memory, can only be retyped into frames.
+    pub is_device: u8,
+    padding: [u8; 6],
+}
+
+/// Everything the root task owns at boot.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct BootInfo {
+    /// Number of logical processors.
+    pub cores: usize,
+    /// Radix of the root CNode, it has no guard left to resolve.
+    pub cnode_radix: usize,
+    /// Root TCB slot.
+    pub tcb: usize,
+    /// Root CNode slot.
+    pub cnode: usize,
+    /// Root VSpace slot.
+    pub vspace: usize,
+    /// IRQ control slot.
+    pub irq_control: usize,
+    /// ASID control slot.
+    pub asid_control: usize,
+    /// Root task ASID pool slot.
+    pub asid_pool: usize,
+    /// Boot information frame slot.
+    pub boot_info: usize,
+    /// IPC buffer frame slot.
+    pub ipc_buffer: usize,
+    /// IPC buffer virtual address.
+    pub ipc_buffer_vaddr: u64,
+    /// Frames backing the root task image and stack.
+    pub user_image: SlotRegion,
+    /// Untyped capabilities, described in `untyped_list`.
+    pub untyped: SlotRegion,
+    /// Free slots.
+    pub empty: SlotRegion,
+    /// Untyped descriptions, in slot order.
+    pub untyped_list: [UntypedDesc; MAX_BOOTINFO_UNTYPED],
+}
+
+const _: () = assert!(size_of::<BootInfo>() <= PAGE_SIZE_4K);
+
+impl BootInfo {
+    /// Describe `root` to the root task.
+    pub fn fill(&mut self, root: &BootCNode, user_image: Range<usize>) {
+        for (desc, idx) in
+            self.untyped_list.iter_mut().zip(root.untyped.clone())
+        {
+            let Ok(ut) = CapRef::<UntypedObj>::try_from(root.slot(idx)) else {
+                continue;
+            };
+            *desc = UntypedDesc {
+                paddr: ut.paddr().as_u64(),
+                size_bits: ut.bit_size() as u8,
+                is_device: ut.is_device() as u8,
+                padding: [0; 6],
+            };
+        }
+
+        self.cores = crate::arch::sysinfo().threads as usize;
+        self.cnode_radix = ROOT_CNODE_RADIX;
+        self.tcb = RootSlot::Tcb as usize;
+        self.cnode = RootSlot::CNode as usize;
+        self.vspace = RootSlot::VSpace as usize;
+        self.irq_control = RootSlot::IrqControl as usize;
+        self.asid_control = RootSlot::AsidControl as usize;
+        self.asid_pool = RootSlot::AsidPool as usize;
+        self.boot_info = RootSlot::BootInfo as usize;
+        self.ipc_buffer = RootSlot::IpcBuffer as usize;
+        self.ipc_buffer_vaddr = IPC_BUFFER_VADDR;
+        self.user_image = user_image.into();
+        self.untyped = root.untyped.clone().into();
+        self.empty = (root.next_free()..ROOT_CNODE_SLOTS).into();
+    }
+}
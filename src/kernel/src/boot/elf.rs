@@ -0,0 +1,237 @@
+//! Minimal ELF64 reader for the root task image.
+
+use crate::error::ElfError;
+use crate::vspace::VMRights;
+
+const MAGIC: [u8; 4] = *b"\x7fELF";
+const CLASS_64: u8 = 2;
+const DATA_LSB: u8 = 1;
+const TYPE_EXEC: u16 = 2;
+const MACHINE_X86_64: u16 = 0x3e;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1 << 0;
+const PF_W: u32 = 1 << 1;
+const PF_R: u32 = 1 << 2;
+
+/// ELF file header.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct Header {
+    ident: [u8; 16],
+    kind: u16,
+    machine: u16,
+    version: u32,
+    entry: u64,
+    phoff: u64,
+    shoff: u64,
+    flags: u32,
+    ehsize: u16,
+    phentsize: u16,
+    phnum: u16,
+    shentsize: u16,
+    shnum: u16,
+    shstrndx: u16,
+}
+
+/// ELF program header.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct ProgramHeader {
+    kind: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    paddr: u64,
+    filesz: u64,
+    memsz: u64,
+    align: u64,
+}
+
+/// Loadable segment.
+#[derive(Debug, Clone, Copy)]
+pub struct Segment<'a> {
+    /// Virtual address of the first byte.
+    pub vaddr: u64,
+    /// Size in memory, bytes past `data` are zeroed.
+    pub memsz: u64,
+    /// File content.
+    pub data: &'a [u8],
+    /// Access rights.
+    pub rights: VMRights,
+}
+
+/// Parsed executable image.
+#[derive(Debug)]
+pub struct Elf<'a> {
+    image: &'a [u8],
+    header: Header,
+}
+
+/// Read a `T` at `offset` in `image`.
+fn read<T: Copy>(image: &[u8], offset: u64) -> Result<T, ElfError> {
+    let offset = usize::try_from(offset).map_err(|_| ElfError::Truncated)?;
+    let end = offset
+        .checked_add(size_of::<T>())
+        .ok_or(ElfError::Truncated)?;
+    let bytes = image.get(offset..end).ok_or(ElfError::Truncated)?;
+
+    // SAFETY: `bytes` holds `size_of::<T>()` bytes, headers are plain
+    // integers.
+    Ok(unsafe { bytes.as_ptr().cast::<T>().read_unaligned() })
+}
+
+impl<'a> Elf<'a> {
+    /// Check `image` is a static x86-64 executable.
+    pub fn parse(image: &'a [u8]) -> Result<Self, ElfError> {
+        let header: Header = read(image, 0)?;
+
+        if header.ident[..4] != MAGIC ||
+            header.ident[4] != CLASS_64 ||
+            header.ident[5] != DATA_LSB ||
+            header.kind != TYPE_EXEC ||
+            header.machine != MACHINE_X86_64 ||
+            usize::from(header.phentsize) != size_of::<ProgramHeader>()
+        {
+            return Err(ElfError::Unsupported);
+        }
+
+        let elf = Self { image, header };
+        for idx in 0..header.phnum {
+            elf.program_header(idx)?;
+        }
+        Ok(elf)
+    }
+
+    /// Entry point.
+    pub fn entry(&self) -> u64 {
+        self.header.entry
+    }
+
+    fn program_header(&self, idx: u16) -> Result<ProgramHeader, ElfError> {
+        let offset = self.header.phoff +
+            u64::from(idx) * size_of::<ProgramHeader>() as u64;
+        read(self.image, offset)
+    }
+
+    /// Iterate over loadable segments.
+    pub fn segments(
+        &self,
+    ) -> impl Iterator<Item = Result<Segment<'a>, ElfError>> + '_ {
+        (0..self.header.phnum)
+            .filter_map(|idx| self.program_header(idx).ok())
+            .filter(|ph| ph.kind == PT_LOAD)
+            .map(|ph| {
+                if ph.filesz > ph.memsz {
+                    return Err(ElfError::InvalidSegment);
+                }
+
+                let start = usize::try_from(ph.offset)
+                    .map_err(|_| ElfError::Truncated)?;
+                let end = start
+                    .checked_add(ph.filesz as usize)
+                    .ok_or(ElfError::Truncated)?;
+                let data =
+                    self.image.get(start..end).ok_or(ElfError::Truncated)?;
+
+                let mut rights = VMRights::NONE;
+                if ph.flags & PF_R != 0 {
+                    rights |= VMRights::READ;
+                }
+                if ph.flags & PF_W != 0 {
+                    rights |= VMRights::WRITE;
+                }
+                if ph.flags & PF_X != 0 {
+                    rights |= VMRights::EXECUTE;
+                }
+
+                Ok(Segment {
+                    vaddr: ph.vaddr,
+                    memsz: ph.memsz,
+                    data,
+                    rights,
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER_SZ: usize = size_of::<Header>();
+    const PH_SZ: usize = size_of::<ProgramHeader>();
+
+    /// Executable with one read-execute segment holding 8 bytes.
+    fn image() -> [u8; HEADER_SZ + PH_SZ + 8] {
+        let mut ident = [0; 16];
+        ident[..4].copy_from_slice(&MAGIC);
+        ident[4] = CLASS_64;
+        ident[5] = DATA_LSB;
+
+        let header = Header {
+            ident,
+            kind: TYPE_EXEC,
+            machine: MACHINE_X86_64,
+            version: 1,
+            entry: 0x40_0010,
+            phoff: HEADER_SZ as u64,
+            shoff: 0,
+            flags: 0,
+            ehsize: HEADER_SZ as u16,
+            phentsize: PH_SZ as u16,
+            phnum: 1,
+            shentsize: 0,
+            shnum: 0,
+            shstrndx: 0,
+        };
+        let ph = ProgramHeader {
+            kind: PT_LOAD,
+            flags: PF_R | PF_X,
+            offset: (HEADER_SZ + PH_SZ) as u64,
+            vaddr: 0x40_0000,
+            paddr: 0,
+            filesz: 8,
+            memsz: 0x1000,
+            align: 0x1000,
+        };
+
+        let mut image = [0xaa; HEADER_SZ + PH_SZ + 8];
+        unsafe {
+            image.as_mut_ptr().cast::<Header>().write_unaligned(header);
+            image
+                .as_mut_ptr()
+                .add(HEADER_SZ)
+                .cast::<ProgramHeader>()
+                .write_unaligned(ph);
+        }
+        image
+    }
+
+    #[test_case]
+    fn parse_loadable_segment() {
+        let image = image();
+        let elf = Elf::parse(&image).unwrap();
+        assert_eq!(elf.entry(), 0x40_0010);
+
+        let mut segments = elf.segments();
+        let segment = segments.next().unwrap().unwrap();
+        assert_eq!(segment.vaddr, 0x40_0000);
+        assert_eq!(segment.memsz, 0x1000);
+        assert_eq!(segment.data, &[0xaa; 8]);
+        assert_eq!(segment.rights, VMRights::RX);
+        assert!(segments.next().is_none());
+    }
+
+    #[test_case]
+    fn parse_rejects_invalid_images() {
+        let mut image = image();
+        assert_eq!(
+            Elf::parse(&image[..HEADER_SZ + 8]).unwrap_err(),
+            ElfError::Truncated
+        );
+
+        image[0] = 0;
+        assert_eq!(Elf::parse(&image).unwrap_err(), ElfError::Unsupported);
+    }
+}
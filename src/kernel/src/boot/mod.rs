@@ -0,0 +1,180 @@
+//! Root task bootstrap.
+//!
+//! Boot objects live in kernel statics and are only touched by the bootstrap
+//! processor before any thread runs.
+
+/// Boot information page.
+pub mod bootinfo;
+
+/// Minimal ELF reader.
+pub mod elf;
+
+/// Root task loading.
+pub mod roottask;
+
+/// Initial untyped capabilities.
+pub mod untyped;
+
+use core::ops::Range;
+
+use crate::arch::VirtAddr;
+use crate::arch::vspace::kernel_paddr;
+use crate::error::{Result, SysError};
+use crate::objects::asid::AsidControlCap;
+use crate::objects::cnode::{CNODE_DEPTH, CNodeCap, CNodeEntry};
+use crate::objects::irq::IrqControlCap;
+use crate::objects::untyped::UntypedObj;
+use crate::objects::{CapRef, CapRights, ObjType};
+use crate::vspace::PAGE_SIZE_4K;
+
+/// Radix of the root CNode.
+pub const ROOT_CNODE_RADIX: usize = 12;
+/// Number of slots in the root CNode.
+pub const ROOT_CNODE_SLOTS: usize = 1 << ROOT_CNODE_RADIX;
+
+/// Well-known slots of the root CNode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum RootSlot {
+    Null = 0,
+    /// Root task TCB.
+    Tcb = 1,
+    /// Root CNode itself.
+    CNode = 2,
+    /// Root task VSpace.
+    VSpace = 3,
+    /// IRQ control.
+    IrqControl = 4,
+    /// ASID control.
+    AsidControl = 5,
+    /// Boot information frame.
+    BootInfo = 6,
+    /// Root task IPC buffer frame.
+    IpcBuffer = 7,
+    /// Root task ASID pool.
+    AsidPool = 8,
+}
+
+/// First slot handed out to boot-time capabilities.
+pub const FIRST_FREE_SLOT: usize = 16;
+
+#[repr(C, align(4096))]
+struct RootCNodeObj([CNodeEntry; ROOT_CNODE_SLOTS]);
+
+// SAFETY: only the bootstrap processor writes it, before threads run.
+unsafe impl Sync for RootCNodeObj {}
+
+static ROOT_CNODE: RootCNodeObj =
+    RootCNodeObj([const { CNodeEntry::new() }; ROOT_CNODE_SLOTS]);
+
+/// Root CNode being filled at boot.
+#[derive(Debug)]
+pub struct BootCNode {
+    next_free: usize,
+    /// Slots holding initial untyped capabilities.
+    pub untyped: Range<usize>,
+}
+
+impl BootCNode {
+    /// Install the root CNode capability in [`RootSlot::CNode`], the IRQ
+    /// control capability in [`RootSlot::IrqControl`] and the ASID control
+    /// capability in [`RootSlot::AsidControl`].
+    ///
+    /// Must be called once.
+    pub fn init() -> Self {
+        let node = &ROOT_CNODE.0;
+        let vaddr = VirtAddr::from_ptr(node.as_ptr());
+        let paddr = kernel_paddr(vaddr).expect("root cnode not mapped");
+
+        // Capabilities reach the CNode through the physical memory window.
+        for offset in (0..size_of_val(node) as u64).step_by(PAGE_SIZE_4K) {
+            assert_eq!(
+                kernel_paddr(vaddr + offset),
+                Some(paddr + offset),
+                "root cnode is not physically contiguous"
+            );
+        }
+
+        node[RootSlot::CNode as usize].set(CNodeCap::mint(
+            paddr.as_u64() as usize,
+            ROOT_CNODE_RADIX,
+            CNODE_DEPTH - ROOT_CNODE_RADIX,
+            0,
+            CapRights::all(),
+        ));
+        node[RootSlot::IrqControl as usize].set(IrqControlCap::mint());
+        node[RootSlot::AsidControl as usize].set(AsidControlCap::mint());
+
+        Self {
+            next_free: FIRST_FREE_SLOT,
+            untyped: 0..0,
+        }
+    }
+
+    /// Get slot `idx`.
+    pub fn slot(&self, idx: usize) -> &'static CNodeEntry {
+        &ROOT_CNODE.0[idx]
+    }
+
+    /// Get well-known `slot`.
+    pub fn root_slot(&self, slot: RootSlot) -> &'static CNodeEntry {
+        self.slot(slot as usize)
+    }
+
+    /// Index of the next free slot.
+    pub fn next_free(&self) -> usize {
+        self.next_free
+    }
+
+    /// Reserve `count` contiguous free slots.
+    pub fn alloc(&mut self, count: usize) -> Option<&'static [CNodeEntry]> {
+        let end = self.next_free.checked_add(count)?;
+        let slots = ROOT_CNODE.0.get(self.next_free..end)?;
+        self.next_free = end;
+        Some(slots)
+    }
+
+    /// Retype `slots` from the first normal untyped with enough room.
+    pub fn retype(
+        &self,
+        obj_type: ObjType,
+        bit_size: usize,
+        slots: &[CNodeEntry],
+    ) -> Result<()> {
+        for idx in self.untyped.clone() {
+            let Ok(ut) = CapRef::<UntypedObj>::try_from(self.slot(idx)) else {
+                continue;
+            };
+            if ut.is_device() {
+                continue;
+            }
+
+            match ut.retype(obj_type, bit_size, slots) {
+                Err(SysError::OutOfMemory) => continue,
+                res => return res,
+            }
+        }
+        Err(SysError::OutOfMemory)
+    }
+
+    /// Make an ASID pool in `slot` from the first normal untyped with room
+    /// left.
+    pub fn make_asid_pool(&self, slot: &CNodeEntry) -> Result<()> {
+        let control =
+            AsidControlCap::try_from(self.root_slot(RootSlot::AsidControl))?;
+        for idx in self.untyped.clone() {
+            let Ok(ut) = CapRef::<UntypedObj>::try_from(self.slot(idx)) else {
+                continue;
+            };
+            if ut.is_device() {
+                continue;
+            }
+
+            match control.make_pool(&ut, slot) {
+                Err(SysError::OutOfMemory) => continue,
+                res => return res,
+            }
+        }
+        Err(SysError::OutOfMemory)
+    }
+}
@@ -0,0 +1,215 @@
+//! Root task, the first user thread, loaded from the bootloader ramdisk.
+
+use core::ptr::NonNull;
+use core::slice;
+
+use crate::arch::{PhysAddr, VirtAddr};
+use crate::boot::bootinfo::{BOOT_INFO_VADDR, BootInfo, IPC_BUFFER_VADDR};
+use crate::boot::elf::{Elf, Segment};
+use crate::boot::{BootCNode, RootSlot};
+use crate::error::{ElfError, Result, SysError, WalkResult};
+use crate::objects::asid::AsidPoolCap;
+use crate::objects::cnode::{CNodeCap, CNodeEntry};
+use crate::objects::frame::FrameCap;
+use crate::objects::tcb::{Tcb, TcbCap};
+use crate::objects::vspace::VSpaceCap;
+use crate::objects::{CapRights, ObjType};
+use crate::vspace::{PAGE_BITS_4K, PAGE_SIZE_4K, VMRights, phys_to_virt};
+use crate::{PHYS_MEM_OFFSET, scheduler};
+
+/// Top of the root task stack.
+pub const STACK_TOP: u64 = 0x7fff_ffff_0000;
+/// Root task stack size, in pages.
+pub const STACK_PAGES: u64 = 16;
+/// First address above user space.
+const USER_TOP: u64 = 0x8000_0000_0000;
+/// Index of RDI in [`crate::arch::trapframe::TrapFrame`] registers.
+const RDI: usize = 5;
+
+/// Load root task `image` and queue it on the boot core.
+///
+/// Its TCB and VSpace go in [`RootSlot::Tcb`] and [`RootSlot::VSpace`],
+/// the VSpace getting its ASID from the pool in [`RootSlot::AsidPool`],
+/// frames backing the image and the stack in the next free slots. The
+/// [`BootInfo`] page and the IPC buffer are mapped last, the former is
+/// passed as first argument.
+pub fn create(root: &mut BootCNode, image: &[u8]) -> Result<()> {
+    let elf = Elf::parse(image)?;
+
+    let vspace_slot = root.root_slot(RootSlot::VSpace);
+    root.retype(ObjType::VSpace, PAGE_BITS_4K, slice::from_ref(vspace_slot))?;
+    let vspace = VSpaceCap::try_from(vspace_slot)?;
+    let pool_slot = root.root_slot(RootSlot::AsidPool);
+    root.make_asid_pool(pool_slot)?;
+    AsidPoolCap::try_from(pool_slot)?.assign(&vspace)?;
+
+    let user_image = root.next_free();
+    for segment in elf.segments() {
+        load_segment(root, &vspace, &segment?)?;
+    }
+
+    let stack_size = STACK_PAGES << PAGE_BITS_4K;
+    let stack = Segment {
+        vaddr: STACK_TOP - stack_size,
+        memsz: stack_size,
+        data: &[],
+        rights: VMRights::RW,
+    };
+    load_segment(root, &vspace, &stack)?;
+    let user_image = user_image..root.next_free();
+
+    let boot_info_slot = root.root_slot(RootSlot::BootInfo);
+    root.retype(
+        ObjType::Frame,
+        PAGE_BITS_4K,
+        slice::from_ref(boot_info_slot),
+    )?;
+    let boot_info = Segment {
+        vaddr: BOOT_INFO_VADDR,
+        memsz: PAGE_SIZE_4K as u64,
+        data: &[],
+        rights: VMRights::READ,
+    };
+    map_frames(root, &vspace, slice::from_ref(boot_info_slot), &boot_info)?;
+
+    let ipc_buffer_slot = root.root_slot(RootSlot::IpcBuffer);
+    root.retype(
+        ObjType::Frame,
+        PAGE_BITS_4K,
+        slice::from_ref(ipc_buffer_slot),
+    )?;
+    let ipc_buffer = Segment {
+        vaddr: IPC_BUFFER_VADDR,
+        memsz: PAGE_SIZE_4K as u64,
+        data: &[],
+        rights: VMRights::RW,
+    };
+    map_frames(root, &vspace, slice::from_ref(ipc_buffer_slot), &ipc_buffer)?;
+
+    let tcb_slot = root.root_slot(RootSlot::Tcb);
+    let tcb_bits = size_of::<Tcb>().ilog2() as usize;
+    root.retype(ObjType::Tcb, tcb_bits, slice::from_ref(tcb_slot))?;
+    let cnode = CNodeCap::try_from(root.root_slot(RootSlot::CNode))?;
+
+    // SAFETY: the TCB was just created, nothing else references it.
+    let tcb = unsafe { TcbCap::try_from(tcb_slot)?.as_object_mut() };
+    tcb.set_roots(&cnode, &vspace)?;
+    tcb.set_ipc_buffer(&FrameCap::try_from(ipc_buffer_slot)?)?;
+    tcb.set_user_entry(elf.entry() as usize, STACK_TOP as usize);
+    tcb.context.registers[RDI] = BOOT_INFO_VADDR as usize;
+
+    // SAFETY: the frame is zeroed and `BootInfo` is plain integers.
+    let boot_info = unsafe {
+        let paddr = FrameCap::try_from(boot_info_slot)?.paddr();
+        &mut *phys_to_virt(paddr).as_mut_ptr::<BootInfo>()
+    };
+    boot_info.fill(root, user_image);
+
+    unsafe { scheduler::enqueue(NonNull::from(tcb)) }
+        .expect("boot core ready queue is full");
+
+    log::info!("root task loaded, entry at {:#x}", elf.entry());
+    Ok(())
+}
+
+/// Back `segment` with fresh frames mapped in `vspace`.
+fn load_segment(
+    root: &mut BootCNode,
+    vspace: &VSpaceCap,
+    segment: &Segment,
+) -> Result<()> {
+    let start = segment.vaddr & !mask!(PAGE_BITS_4K);
+    let end = segment
+        .vaddr
+        .checked_add(segment.memsz)
+        .filter(|&end| end <= USER_TOP)
+        .ok_or(ElfError::InvalidSegment)?;
+    let count =
+        ((alignup!(end, PAGE_BITS_4K) - start) >> PAGE_BITS_4K) as usize;
+
+    let frames = root.alloc(count).ok_or(SysError::OutOfMemory)?;
+    root.retype(ObjType::Frame, PAGE_BITS_4K, frames)?;
+    map_frames(root, vspace, frames, segment)
+}
+
+/// Fill `frames` with `segment` content and map them in `vspace`.
+fn map_frames(
+    root: &BootCNode,
+    vspace: &VSpaceCap,
+    frames: &[CNodeEntry],
+    segment: &Segment,
+) -> Result<()> {
+    let start = segment.vaddr & !mask!(PAGE_BITS_4K);
+
+    let mut rights = CapRights::NONE;
+    if segment.rights.contains(VMRights::READ) {
+        rights |= CapRights::READ;
+    }
+    if segment.rights.contains(VMRights::WRITE) {
+        rights |= CapRights::WRITE;
+    }
+    if segment.rights.contains(VMRights::EXECUTE) {
+        rights |= CapRights::EXECUTE;
+    }
+
+    let data_end = segment.vaddr + segment.data.len() as u64;
+    for (i, slot) in frames.iter().enumerate() {
+        let vaddr = start + ((i as u64) << PAGE_BITS_4K);
+
+        let mut raw = slot.get();
+        raw.rights = rights;
+        slot.set(raw);
+        let frame = FrameCap::try_from(slot)?;
+
+        // SAFETY: the frame was just retyped, the kernel owns it.
+        let page = unsafe {
+            slice::from_raw_parts_mut(
+                phys_to_virt(frame.paddr()).as_mut_ptr::<u8>(),
+                PAGE_SIZE_4K,
+            )
+        };
+        page.fill(0);
+
+        let copy_start = vaddr.max(segment.vaddr);
+        let copy_end = (vaddr + PAGE_SIZE_4K as u64).min(data_end);
+        if copy_start < copy_end {
+            let src = &segment.data[(copy_start - segment.vaddr) as usize..
+                (copy_end - segment.vaddr) as usize];
+            page[(copy_start - vaddr) as usize..(copy_end - vaddr) as usize]
+                .copy_from_slice(src);
+        }
+
+        let vaddr = VirtAddr::new(vaddr);
+        install_tables(root, vspace, vaddr)?;
+        unsafe { vspace.map_frame::<PHYS_MEM_OFFSET>(vaddr, &frame, true)? };
+        frame.set_mapped(vspace.asid(), vaddr.as_u64() as usize)?;
+    }
+
+    Ok(())
+}
+
+/// Install missing paging structures down to the page table of `vaddr`.
+fn install_tables(
+    root: &BootCNode,
+    vspace: &VSpaceCap,
+    vaddr: VirtAddr,
+) -> Result<()> {
+    loop {
+        let level = match unsafe { vspace.walk::<PHYS_MEM_OFFSET>(vaddr)? } {
+            WalkResult::NotMapped { level } if level > 1 => level - 1,
+            _ => return Ok(()),
+        };
+
+        // Paging structures are kernel memory, no capability is kept.
+        let table = CNodeEntry::new();
+        root.retype(ObjType::Frame, PAGE_BITS_4K, slice::from_ref(&table))?;
+        let paddr = PhysAddr::new(table.get().paddr as u64);
+
+        unsafe {
+            phys_to_virt(paddr)
+                .as_mut_ptr::<u8>()
+                .write_bytes(0, PAGE_SIZE_4K);
+            vspace.install_table::<PHYS_MEM_OFFSET>(vaddr, level, paddr)?;
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Untyped capabilities covering the bootloader memory map.
+
+use bootloader_api::info::{MemoryRegion, MemoryRegionKind};
+
+use crate::boot::BootCNode;
+use crate::boot::bootinfo::MAX_BOOTINFO_UNTYPED;
+use crate::objects::CapRef;
+use crate::objects::untyped::UntypedObj;
+use crate::vspace::PAGE_BITS_4K;
+
+/// Largest initial untyped, in bits.
+pub const MAX_UNTYPED_BITS: usize = 47;
+
+/// `EfiReservedMemoryType`.
+const UEFI_RESERVED: u32 = 0;
+/// `EfiMemoryMappedIO`.
+const UEFI_MMIO: u32 = 11;
+/// `EfiMemoryMappedIOPortSpace`.
+const UEFI_MMIO_PORT_SPACE: u32 = 12;
+/// E820 reserved range.
+const BIOS_RESERVED: u32 = 2;
+/// End of conventional memory, kept for the AP trampoline.
+pub const LOW_MEMORY_END: u64 = 0x10_0000;
+
+/// Whether `kind` becomes normal or device untyped memory.
+fn is_device(kind: MemoryRegionKind) -> Option<bool> {
+    match kind {
+        MemoryRegionKind::Usable => Some(false),
+        MemoryRegionKind::UnknownUefi(
+            UEFI_RESERVED | UEFI_MMIO | UEFI_MMIO_PORT_SPACE,
+        ) => Some(true),
+        MemoryRegionKind::UnknownBios(BIOS_RESERVED) => Some(true),
+        _ => None,
+    }
+}
+
+/// Naturally aligned power-of-two blocks covering a physical range.
+#[derive(Debug, Clone)]
+pub struct Blocks {
+    start: u64,
+    end: u64,
+}
+
+impl Blocks {
+    /// Split the pages fully inside `[start, end)`.
+    pub fn new(start: u64, end: u64) -> Self {
+        let start = alignup!(start, PAGE_BITS_4K);
+        let end = end & !mask!(PAGE_BITS_4K);
+        Self {
+            start,
+            end: end.max(start),
+        }
+    }
+}
+
+impl Iterator for Blocks {
+    /// Block base address and size in bits.
+    type Item = (u64, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.start >= self.end {
+            return None;
+        }
+
+        let align_bits = self.start.trailing_zeros() as usize;
+        let size_bits = (self.end - self.start).ilog2() as usize;
+        let bits = align_bits.min(size_bits).min(MAX_UNTYPED_BITS);
+
+        let block = (self.start, bits);
+        self.start += 1 << bits;
+        Some(block)
+    }
+}
+
+/// Create untyped capabilities for usable, reserved and MMIO `regions`.
+///
+/// Adjacent regions of the same nature are merged first to get larger
+/// blocks. Usable memory below [`LOW_MEMORY_END`] is left out. Slots are
+/// taken from `root` and recorded in `root.untyped`, at most
+/// [`MAX_BOOTINFO_UNTYPED`] so that boot info describes all of them.
+pub fn create_untypeds(root: &mut BootCNode, regions: &[MemoryRegion]) {
+    let first = root.next_free();
+    let last = first + MAX_BOOTINFO_UNTYPED;
+    let mut pending: Option<(u64, u64, bool)> = None;
+
+    for region in regions {
+        let Some(device) = is_device(region.kind) else {
+            continue;
+        };
+        let start = if device {
+            region.start
+        } else {
+            region.start.max(LOW_MEMORY_END)
+        };
+        if start >= region.end {
+            continue;
+        }
+
+        if let Some((_, end, pending_device)) = &mut pending &&
+            *end == start &&
+            *pending_device == device
+        {
+            *end = region.end;
+            continue;
+        }
+
+        if let Some((start, end, device)) =
+            pending.replace((start, region.end, device)) &&
+            !insert_range(root, last, start, end, device)
+        {
+            pending = None;
+            break;
+        }
+    }
+
+    if let Some((start, end, device)) = pending {
+        insert_range(root, last, start, end, device);
+    }
+
+    root.untyped = first..root.next_free();
+    log::info!("{} untyped capabilities created", root.untyped.len());
+}
+
+/// Insert untypeds covering `[start, end)` in slots before `last`.
+///
+/// Return `false` once slots are exhausted.
+fn insert_range(
+    root: &mut BootCNode,
+    last: usize,
+    start: u64,
+    end: u64,
+    device: bool,
+) -> bool {
+    log::debug!("untyped {start:#x}..{end:#x} (device: {device})");
+
+    for (paddr, bits) in Blocks::new(start, end) {
+        if root.next_free() >= last {
+            log::warn!("out of untyped slots, {paddr:#x} dropped");
+            return false;
+        }
+        let Some([slot]) = root.alloc(1) else {
+            log::warn!("root cnode full, untyped {paddr:#x} dropped");
+            return false;
+        };
+        slot.set(CapRef::<UntypedObj>::mint(paddr as usize, bits, device));
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn blocks_follow_alignment() {
+        let mut blocks = Blocks::new(0x1000, 0x8000);
+        assert_eq!(blocks.next(), Some((0x1000, 12)));
+        assert_eq!(blocks.next(), Some((0x2000, 13)));
+        assert_eq!(blocks.next(), Some((0x4000, 14)));
+        assert_eq!(blocks.next(), None);
+    }
+
+    #[test_case]
+    fn blocks_skip_partial_pages() {
+        let mut blocks = Blocks::new(0x1234, 0x3fff);
+        assert_eq!(blocks.next(), Some((0x2000, 12)));
+        assert_eq!(blocks.next(), None);
+
+        assert_eq!(Blocks::new(0x1001, 0x1fff).next(), None);
+    }
+
+    #[test_case]
+    fn blocks_capped_size() {
+        let mut blocks = Blocks::new(0, 1 << 48);
+        assert_eq!(blocks.next(), Some((0, MAX_UNTYPED_BITS)));
+        assert_eq!(
+            blocks.next(),
+            Some((1 << MAX_UNTYPED_BITS, MAX_UNTYPED_BITS))
+        );
+        assert_eq!(blocks.next(), None);
+    }
+}
@@ -148,3 +148,85 @@ impl<'a> CSpace<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::CapRights;
+    use crate::objects::endpoint::EndpointCap;
+    use crate::testing::{NODE_RADIX, Node};
+
+    fn cnode_cap(node: &Node, guard_bits: usize) -> CNodeEntry {
+        let entry = CNodeEntry::new();
+        entry.set(CNodeCap::mint(
+            node.paddr(),
+            NODE_RADIX,
+            guard_bits,
+            0,
+            CapRights::CONTROL,
+        ));
+        entry
+    }
+
+    #[test_case]
+    fn resolve_single_level() {
+        let node = Node::new();
+        node.0[3].set(EndpointCap::mint(0x1000, 0, CapRights::SEND));
+        let root = cnode_cap(&node, CNODE_DEPTH - NODE_RADIX);
+
+        let cspace = CSpace::new(&root).unwrap();
+        let slot = cspace.lookup(3).unwrap();
+        assert!(core::ptr::eq(slot, node.slot(3)));
+        assert_eq!(slot.get().cap_type, ObjType::Endpoint);
+    }
+
+    #[test_case]
+    fn resolve_guard_mismatch() {
+        let node = Node::new();
+        let root = cnode_cap(&node, CNODE_DEPTH - NODE_RADIX);
+
+        let cspace = CSpace::new(&root).unwrap();
+        let err = cspace.lookup(1 << 20).unwrap_err();
+        assert_eq!(err, SysError::LookupError);
+    }
+
+    #[test_case]
+    fn resolve_two_levels() {
+        let leaf = Node::new();
+        leaf.0[7].set(EndpointCap::mint(0x2000, 0, CapRights::RECEIVE));
+
+        // 24 bits on first level, 8 bits on second level.
+        let top = Node::new();
+        top.0[2].set(CNodeCap::mint(
+            leaf.paddr(),
+            NODE_RADIX,
+            NODE_RADIX,
+            0,
+            CapRights::CONTROL,
+        ));
+        let root = cnode_cap(&top, 20);
+
+        let cspace = CSpace::new(&root).unwrap();
+        let slot = cspace.lookup((2 << 8) | 7).unwrap();
+        assert!(core::ptr::eq(slot, leaf.slot(7)));
+    }
+
+    #[test_case]
+    fn resolve_stops_on_leaf_cap() {
+        let top = Node::new();
+        top.0[2].set(EndpointCap::mint(0x3000, 0, CapRights::SEND));
+        let root = cnode_cap(&top, 20);
+
+        let cspace = CSpace::new(&root).unwrap();
+        let res = cspace.resolve(2 << 8, CNODE_DEPTH).unwrap();
+        assert!(core::ptr::eq(res.slot, top.slot(2)));
+        assert_eq!(res.bits_remaining, 8);
+        assert_eq!(cspace.lookup(2 << 8).unwrap_err(), SysError::LookupError);
+    }
+
+    #[test_case]
+    fn cspace_requires_cnode_root() {
+        let root = CNodeEntry::new();
+        assert_eq!(CSpace::new(&root).unwrap_err(), SysError::CSpaceNotFound);
+    }
+}
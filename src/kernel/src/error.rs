@@ -1,5 +1,7 @@
 //! Custom kernel errors.
 
+use core::fmt;
+
 pub type Result<T> = core::result::Result<T, SysError>;
 
 /// Custom system error.
@@ -29,6 +31,7 @@ pub enum SysError {
     RangeError,
     RevokeFailed,
     DeleteFailed,
+    UnknownSyscall,
 }
 
 impl SysError {
@@ -45,6 +48,14 @@ impl SysError {
     }
 }
 
+impl fmt::Display for SysError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl core::error::Error for SysError {}
+
 #[derive(Debug, Clone, Copy)]
 pub enum WalkResult {
     /// Found a mapped page at the given level.
@@ -62,7 +73,7 @@ pub enum WalkResult {
 /// Error during page table operations.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VSpaceError {
-    /// Virtual address is not canonical.
+    /// Virtual address is not canonical, or not in the user half.
     InvalidVAddr,
     /// Physical address is not properly aligned.
     MisalignedPAddr,
@@ -97,3 +108,20 @@ impl From<VSpaceError> for SysError {
         }
     }
 }
+
+/// Error while loading an ELF image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    /// Image is shorter than its headers claim.
+    Truncated,
+    /// Not a 64-bit little-endian x86-64 executable.
+    Unsupported,
+    /// Loadable segment outside of user space or overlapping another.
+    InvalidSegment,
+}
+
+impl From<ElfError> for SysError {
+    fn from(_: ElfError) -> Self {
+        SysError::InvalidValue
+    }
+}
@@ -5,6 +5,9 @@
 #![no_main]
 #![feature(abi_x86_interrupt)]
 #![allow(unsafe_op_in_unsafe_fn, dead_code)]
+#![cfg_attr(test, feature(custom_test_frameworks))]
+#![cfg_attr(test, test_runner(crate::testing::runner))]
+#![cfg_attr(test, reexport_test_harness_main = "test_main")]
 
 mod arch;
 mod error;
@@ -13,7 +16,10 @@ mod scheduler;
 mod syscall;
 #[macro_use]
 mod macros;
+mod boot;
 mod cspace;
+#[cfg(test)]
+mod testing;
 mod vspace;
 
 use bootloader_api::config::Mapping;
@@ -40,6 +46,8 @@ pub static BOOTLOADER_CONFIG: BootloaderConfig = {
         Some(Mapping::FixedAddress(PHYS_MEM_OFFSET));
     config.mappings.page_table_recursive =
         Some(Mapping::FixedAddress(RECURSIVE_P4_ADDR));
+    // Kernel and its boot mappings live in the half shared by VSpaces.
+    config.mappings.dynamic_range_start = Some(PHYS_MEM_OFFSET);
 
     config
 };
@@ -51,8 +59,13 @@ pub static TICKS: Lazy<Mutex<arch::tick::Tick>> =
 entry_point!(main, config = &BOOTLOADER_CONFIG);
 
 fn main(boot_info: &'static mut BootInfo) -> ! {
+    arch::percpu::init(0);
+
+    #[cfg(any(feature = "framebuffer", feature = "serial"))]
+    arch::console::init();
+
     #[cfg(feature = "framebuffer")]
-    arch::console::init(
+    arch::console::attach_framebuffer(
         boot_info
             .framebuffer
             .take()
@@ -66,37 +79,79 @@ fn main(boot_info: &'static mut BootInfo) -> ! {
             .expect("physical memory offset undefined"),
     );
 
+    let mut root_cnode = boot::BootCNode::init();
+    boot::untyped::create_untypeds(&mut root_cnode, &boot_info.memory_regions);
+
     let rsdp_addr = boot_info
         .rsdp_addr
         .take()
         .expect("Failed to find RSDP address");
-    let apic = APIC
-        .lock()
-        .init(rsdp_addr as usize, physical_memory_offset.as_u64());
+    let madt = arch::acpi::init(rsdp_addr as usize, physical_memory_offset);
+    arch::topology::init(madt);
+    let apic = APIC.lock().init(madt, physical_memory_offset.as_u64());
     *APIC.lock() = apic;
 
     // Enable interrupts after disabling PIC.
     arch::interrupts::load();
+    arch::vspace::init_pcid();
 
     let ticks = TICKS.lock().clone().calibrate(apic);
     *TICKS.lock() = ticks;
 
     scheduler::init_scheduler();
+    arch::ipi::init();
+    arch::smp::init(&boot_info.memory_regions);
+
+    arch::vspace::init_kernel_half();
+
+    match boot_info.ramdisk_addr.into_option() {
+        Some(addr) => {
+            // SAFETY: the bootloader maps the ramdisk at `addr`.
+            let image = unsafe {
+                core::slice::from_raw_parts(
+                    addr as *const u8,
+                    boot_info.ramdisk_len as usize,
+                )
+            };
+            boot::roottask::create(&mut root_cnode, image)
+                .expect("failed to load root task");
+        },
+        None => log::warn!("no ramdisk, root task not loaded"),
+    }
 
     // Enable syscalls.
     arch::syscall::init_syscall();
 
-    let executor = scheduler::SCHEDULER.get().unwrap().get_mut();
-    executor.run()
+    #[cfg(test)]
+    test_main();
+
+    unsafe { scheduler::executor() }.run()
 }
 
 /// Handle panics.
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
+    #[cfg(feature = "serial")]
+    arch::console::serial::panic_print(format_args!("KERNEL PANIC: {info}\n"));
+
     #[cfg(feature = "framebuffer")]
-    if let Some(logger) = arch::console::logger::LOGGER.get() {
-        logger.framebuffer.try_lock().unwrap().panic_screen();
+    if let Some(framebuffer) = arch::console::logger::LOGGER.framebuffer.get()
+    {
+        use core::fmt::Write;
+
+        if let Some(mut framebuffer) = framebuffer.try_lock() {
+            framebuffer.panic_screen();
+            let _ = writeln!(framebuffer, "KERNEL PANIC: {info}");
+        }
     }
-    log::error!("KERNEL PANIC: {info:?}");
+
     loop {}
 }
+
+/// Handle panics, failing the running test.
+#[cfg(test)]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    testing::panic(info)
+}
@@ -0,0 +1,248 @@
+//! ASID capabilities.
+//!
+//! The ASID control capability makes ASID pools out of untyped memory, up
+//! to [`ASID_POOLS`] of them. Each pool hands out the ASIDs of its range to
+//! VSpaces, ASID 0 standing for a VSpace without one. Every ASID fits in a
+//! PCID, so that TLB entries of a VSpace survive context switches.
+//!
+//! Deleting the last capability to a VSpace releases its ASID, once its TLB
+//! entries are invalidated on every core. VSpace capabilities are only
+//! copied once they have an ASID.
+
+use spin::Mutex;
+
+use crate::arch::PhysAddr;
+use crate::arch::ipi::shootdown_asid;
+use crate::error::{Result, SysError};
+use crate::mask;
+use crate::objects::cnode::CNodeEntry;
+use crate::objects::untyped::UntypedObj;
+use crate::objects::vspace::{ASID_MAX, Asid, VSpaceCap};
+use crate::objects::{CapRaw, CapRef, CapRights, ObjType};
+use crate::vspace::{PAGE_SIZE_4K, phys_to_virt};
+
+/// Number of ASIDs in a pool, bits below its base.
+pub const ASID_POOL_BITS: usize = 9;
+/// Number of ASID pools.
+pub const ASID_POOLS: usize = 8;
+
+const ASID_POOL_SIZE: usize = 1 << ASID_POOL_BITS;
+
+/// Base address of each pool, by ASID high bits.
+static POOLS: Mutex<[Option<PhysAddr>; ASID_POOLS]> =
+    Mutex::new([None; ASID_POOLS]);
+
+/// Pool entry of `asid`, whether assigned or not.
+///
+/// # Safety
+/// Pools lock must be held.
+unsafe fn entry(
+    pools: &[Option<PhysAddr>; ASID_POOLS],
+    asid: Asid,
+) -> Option<&'static mut u64> {
+    let pool = (*pools.get(asid as usize >> ASID_POOL_BITS)?)?;
+    let pool = &mut *phys_to_virt(pool).as_mut_ptr::<AsidPoolObj>();
+    Some(&mut pool.vspaces[asid as usize & mask!(ASID_POOL_BITS)])
+}
+
+/// Release the ASID of `vspace`, when its last capability is deleted.
+pub fn delete_asid(vspace: &VSpaceCap) {
+    let asid = vspace.asid();
+    let root = vspace.root_paddr().as_u64();
+    let assigned = |pools: &[Option<PhysAddr>; ASID_POOLS]| {
+        // SAFETY: pools lock is held by the caller.
+        unsafe { entry(pools, asid) }.filter(|entry| **entry == root)
+    };
+
+    if asid == 0 || assigned(&POOLS.lock()).is_none() {
+        return;
+    }
+
+    // No core may keep entries of the ASID once it is reused. Other cores
+    // serve the shootdown, they must not wait on the pools lock.
+    shootdown_asid(asid);
+    if let Some(entry) = assigned(&POOLS.lock()) {
+        *entry = 0;
+    }
+}
+
+#[derive(Debug)]
+pub enum AsidControlObj {}
+
+pub type AsidControlCap<'a> = CapRef<'a, AsidControlObj>;
+
+impl AsidControlCap<'_> {
+    /// Create the ASID control capability.
+    pub const fn mint() -> CapRaw {
+        let mut capraw = CapRaw::default_with_type(ObjType::AsidControl);
+        capraw.rights = CapRights::all();
+        capraw
+    }
+
+    /// Make an ASID pool out of `untyped` in empty slot `dst`.
+    pub fn make_pool(
+        &self,
+        untyped: &CapRef<'_, UntypedObj>,
+        dst: &CNodeEntry,
+    ) -> Result<()> {
+        if !dst.is_null() {
+            return Err(SysError::SlotNotEmpty);
+        }
+
+        let mut pools = POOLS.lock();
+        let (index, pool) = pools
+            .iter_mut()
+            .enumerate()
+            .find(|(_, pool)| pool.is_none())
+            .ok_or(SysError::RangeError)?;
+        let paddr = untyped.alloc_page()?;
+        *pool = Some(paddr);
+
+        dst.set(AsidPoolCap::mint(paddr, (index << ASID_POOL_BITS) as Asid));
+        CNodeEntry::mdb_insert_after(self.raw, dst);
+        Ok(())
+    }
+}
+
+/// VSpace roots of the ASIDs of a pool, 0 for free ones.
+#[repr(C)]
+pub struct AsidPoolObj {
+    vspaces: [u64; ASID_POOL_SIZE],
+}
+
+const _: () = assert!(size_of::<AsidPoolObj>() == PAGE_SIZE_4K);
+
+pub type AsidPoolCap<'a> = CapRef<'a, AsidPoolObj>;
+
+impl AsidPoolCap<'_> {
+    /// Create a capability to the pool at `paddr`, starting at ASID `base`.
+    pub const fn mint(paddr: PhysAddr, base: Asid) -> CapRaw {
+        let mut capraw = CapRaw::default_with_type(ObjType::AsidPool);
+        capraw.paddr = paddr.as_u64() as usize;
+        capraw.arg1 = base as usize;
+        capraw.rights = CapRights::all();
+        capraw
+    }
+
+    /// First ASID of the pool.
+    pub fn base(&self) -> Asid {
+        self.raw.get().arg1 as Asid
+    }
+
+    /// Assign a free ASID of the pool to `vspace`.
+    ///
+    /// A VSpace capability is only derived once it has an ASID, so that
+    /// every copy shares it.
+    pub fn assign(&self, vspace: &VSpaceCap) -> Result<()> {
+        let root = vspace.root_paddr().as_u64();
+        if vspace.asid() != 0 {
+            return Err(SysError::InvalidOperation);
+        }
+
+        let pools = POOLS.lock();
+        // SAFETY: pools lock is held.
+        let assigned = (1..=ASID_MAX)
+            .filter_map(|asid| unsafe { entry(&pools, asid) })
+            .any(|entry| *entry == root);
+        if assigned {
+            return Err(SysError::InvalidOperation);
+        }
+
+        // SAFETY: pool pages are only reached with the pools lock held.
+        let pool = unsafe {
+            &mut *phys_to_virt(self.paddr()).as_mut_ptr::<AsidPoolObj>()
+        };
+        let base = self.base();
+        let (offset, entry) = pool
+            .vspaces
+            .iter_mut()
+            .enumerate()
+            .find(|(offset, entry)| {
+                **entry == 0 && base as usize + offset != 0
+            })
+            .ok_or(SysError::OutOfMemory)?;
+
+        *entry = root;
+        vspace.set_asid(base + offset as Asid);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Page;
+
+    #[test_case]
+    fn assign_distinct_asids() {
+        let page = Page::new();
+        let pool = CNodeEntry::new();
+        pool.set(AsidPoolCap::mint(page.paddr(), 0));
+        let pool = AsidPoolCap::try_from(&pool).unwrap();
+
+        let vspaces = [const { CNodeEntry::new() }; 2];
+        for (i, vspace) in vspaces.iter().enumerate() {
+            vspace.set(VSpaceCap::mint((i + 1) << 12, 0, CapRights::all()));
+        }
+        let first = VSpaceCap::try_from(&vspaces[0]).unwrap();
+        let second = VSpaceCap::try_from(&vspaces[1]).unwrap();
+
+        // ASID 0 is never assigned.
+        pool.assign(&first).unwrap();
+        pool.assign(&second).unwrap();
+        assert_eq!(first.asid(), 1);
+        assert_eq!(second.asid(), 2);
+        assert_eq!(pool.assign(&first), Err(SysError::InvalidOperation));
+    }
+
+    #[test_case]
+    fn delete_releases_asid() {
+        let page = Page::new();
+        let index = {
+            let mut pools = POOLS.lock();
+            let index = pools.iter().position(Option::is_none).unwrap();
+            pools[index] = Some(page.paddr());
+            index
+        };
+        let base = (index << ASID_POOL_BITS) as Asid;
+        let pool = CNodeEntry::new();
+        pool.set(AsidPoolCap::mint(page.paddr(), base));
+        let pool = AsidPoolCap::try_from(&pool).unwrap();
+
+        let root = 3 << 12;
+        let [first, middle, last, other] = [const { CNodeEntry::new() }; 4];
+        first.set(VSpaceCap::mint(root, 0, CapRights::all()));
+        other.set(VSpaceCap::mint(root, 0, CapRights::all()));
+        let cap = VSpaceCap::try_from(&first).unwrap();
+        pool.assign(&cap).unwrap();
+        let asid = cap.asid();
+        // SAFETY: pools lock is held.
+        let assigned = || unsafe { *entry(&POOLS.lock(), asid).unwrap() };
+
+        // The same root never gets a second ASID.
+        let other = VSpaceCap::try_from(&other).unwrap();
+        assert_eq!(pool.assign(&other), Err(SysError::InvalidOperation));
+
+        for (src, dst) in [(&first, &middle), (&middle, &last)] {
+            let mut raw = src.get();
+            raw.mdb_prev = None;
+            raw.mdb_next = None;
+            dst.set(raw);
+            CNodeEntry::mdb_insert_after(src, dst);
+        }
+
+        // Copies keep the ASID until the last capability is deleted.
+        for copy in [&middle, &first] {
+            copy.delete();
+            assert_eq!(assigned(), root as u64);
+        }
+        last.delete();
+        assert_eq!(assigned(), 0);
+        assert!(last.is_null());
+
+        pool.assign(&other).unwrap();
+        assert_eq!(other.asid(), asid);
+
+        POOLS.lock()[index] = None;
+    }
+}
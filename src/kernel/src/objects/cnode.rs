@@ -6,9 +6,15 @@ use core::slice;
 
 use vstd::prelude::*;
 
+use crate::arch::PhysAddr;
 use crate::error::{Result as SysResult, SysError};
+use crate::objects::asid::delete_asid;
+use crate::objects::irq::{self, IrqHandlerCap};
+use crate::objects::notification::NotificationCap;
 use crate::objects::traits::KernelObject;
+use crate::objects::vspace::VSpaceCap;
 use crate::objects::{CapRaw, CapRef, CapRights, ObjType};
+use crate::vspace::phys_to_virt;
 
 verus! {
 
@@ -119,6 +125,10 @@ impl CNodeEntry {
     }
 
     /// Insert `dst` after `src` on MDB list.
+    ///
+    /// Every copy of a capability must be inserted right after its source,
+    /// so that capabilities to an object stay contiguous and
+    /// [`Self::is_final`] only has to look at neighbours.
     pub fn mdb_insert_after(src: &CNodeEntry, dst: &CNodeEntry)
         requires
             dst.mdb_isolated(),
@@ -217,23 +227,8 @@ impl CNodeEntry {
         while let Some(ptr) = cur {
             unsafe {
                 let entry = ptr.as_ref();
-                let mut raw = entry.get();
-                cur = raw.mdb_next;
-
-                // Erase capability.
-                raw.cap_type = ObjType::NullObj;
-                raw.rights = CapRights::NONE;
-                raw.paddr = 0;
-                raw.arg1 = 0;
-                raw.arg2 = 0;
-                entry.set(raw);
-
-                proof {
-                    assert(Self::is_nullified(entry.view()));
-                }
-
-                // Remove from chain.
-                entry.mdb_remove();
+                cur = entry.get().mdb_next;
+                entry.delete();
 
                 proof {
                     assert(entry.mdb_isolated());
@@ -241,6 +236,60 @@ impl CNodeEntry {
             }
         }
     }
+
+    /// Whether no MDB neighbour refers to the object of this capability.
+    ///
+    /// Relies on copies being inserted right after their source, see
+    /// [`Self::mdb_insert_after`].
+    pub fn is_final(&self) -> bool {
+        let raw = self.get();
+        let mut neighbours =
+            [raw.mdb_prev, raw.mdb_next].into_iter().flatten();
+        !neighbours.any(|ptr| {
+            // SAFETY: MDB links only point to live entries.
+            let other = unsafe { ptr.as_ref() }.get();
+            // Handlers have no object, they stand for their line.
+            let same_line =
+                raw.cap_type != ObjType::Interrupt || other.arg1 == raw.arg1;
+            other.cap_type == raw.cap_type && other.paddr == raw.paddr &&
+                same_line
+        })
+    }
+
+    /// Delete capability and remove it from MDB.
+    ///
+    /// Deleting the last capability to an object releases what the kernel
+    /// holds for it, such as the ASID of a VSpace or an interrupt line.
+    pub fn delete(&self)
+        ensures
+            self.mdb_isolated(),
+    {
+        if self.is_final() {
+            if let Ok(vspace) = VSpaceCap::try_from(self) {
+                delete_asid(&vspace);
+            } else if let Ok(handler) = IrqHandlerCap::try_from(self) {
+                irq::release(handler.gsi());
+            } else if let Ok(ntfn) = NotificationCap::try_from(self) {
+                irq::detach(&ntfn);
+            }
+        }
+
+        // Erase capability.
+        let mut raw = self.get();
+        raw.cap_type = ObjType::NullObj;
+        raw.rights = CapRights::NONE;
+        raw.paddr = 0;
+        raw.arg1 = 0;
+        raw.arg2 = 0;
+        self.set(raw);
+
+        proof {
+            assert(Self::is_nullified(self.view()));
+        }
+
+        // Remove from chain.
+        self.mdb_remove();
+    }
 }
 
 pub type CNodeCap<'a> = CapRef<'a, CNodeObj>;
@@ -450,7 +499,8 @@ impl CNodeCap<'_> {
     {
         let raw = self.get_raw();
         let size = 1usize << self.radix_bits();
-        unsafe { slice::from_raw_parts(raw.paddr as *const CNodeEntry, size) }
+        let ptr = phys_to_virt(PhysAddr::new(raw.paddr as u64)).as_ptr();
+        unsafe { slice::from_raw_parts(ptr, size) }
     }
 
     /// Get the object as a mutable slice.
@@ -466,7 +516,8 @@ impl CNodeCap<'_> {
     {
         let raw = self.get_raw();
         let size = 1usize << self.radix_bits();
-        unsafe { slice::from_raw_parts_mut(raw.paddr as *mut CNodeEntry, size) }
+        let ptr = phys_to_virt(PhysAddr::new(raw.paddr as u64)).as_mut_ptr();
+        unsafe { slice::from_raw_parts_mut(ptr, size) }
     }
 
     pub fn init(&self)
@@ -2,10 +2,23 @@
 
 use core::ptr::NonNull;
 
-use crate::error::Result;
-use crate::objects::tcb::{IpcState, Tcb, TcbQueue, ThreadState};
+use crate::arch::{PhysAddr, VirtAddr};
+use crate::cspace::CSpace;
+use crate::error::{Result, SysError};
+use crate::objects::cnode::CNodeEntry;
+use crate::objects::frame::FrameCap;
+use crate::objects::message::{IpcBuffer, MSG_REGISTERS, MessageInfo};
+use crate::objects::notification::{NotificationObj, NotificationState};
+use crate::objects::tcb::{Fault, IpcState, Tcb, TcbQueue, ThreadState};
+use crate::objects::vspace::VSpaceCap;
 use crate::objects::{CapRaw, CapRef, CapRights, ObjType};
-use crate::scheduler::SCHEDULER;
+use crate::scheduler;
+use crate::vspace::phys_to_virt;
+
+/// Endpoint object size, in bits.
+pub const ENDPOINT_BIT_SZ: usize = 5;
+
+const _: () = assert!(size_of::<EndpointObj>() <= 1 << ENDPOINT_BIT_SZ);
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -88,16 +101,21 @@ impl EndpointCap<'_> {
         self.rights().contains(CapRights::GRANT)
     }
 
+    #[inline]
+    pub fn can_grant_reply(&self) -> bool {
+        self.rights().contains(CapRights::GRANT_REPLY)
+    }
+
     /// Get a mutable reference to the endpoint object.
     ///
     /// # Safety
     /// Caller must ensure exclusive access.
     unsafe fn as_object_mut(&self) -> &'static mut EndpointObj {
-        &mut *(self.paddr().as_u64() as *mut EndpointObj)
+        &mut *phys_to_virt(self.paddr()).as_mut_ptr::<EndpointObj>()
     }
 
     unsafe fn as_object(&self) -> &'static EndpointObj {
-        &*(self.paddr().as_u64() as *const EndpointObj)
+        &*phys_to_virt(self.paddr()).as_ptr::<EndpointObj>()
     }
 
     pub fn identify(&self, tcb: &mut Tcb) -> usize {
@@ -111,27 +129,173 @@ impl EndpointCap<'_> {
 
 /// Perform IPC transfer from sender to receiver.
 ///
+/// `endpoint` is the object the message went through, if any, capabilities
+/// to it are unwrapped into badges.
+///
 /// # Safety
 /// Both TCB pointers must be valid.
 unsafe fn do_ipc_transfer(
     sender: NonNull<Tcb>,
     receiver: NonNull<Tcb>,
+    endpoint: Option<PhysAddr>,
     badge: usize,
     can_grant: bool,
 ) {
     let sender_ref = sender.as_ref();
     let receiver_ptr = receiver.as_ptr();
+    if let Some(fault) = sender_ref.fault() {
+        do_fault_transfer(fault, sender_ref, &mut *receiver_ptr, badge);
+        return;
+    }
 
+    let info = MessageInfo::from_word(sender_ref.get_mr(Tcb::MR2));
+
+    // Message words follow badge and tag, then overflow in IPC buffers.
+    let mut length = info.length().min(MSG_REGISTERS);
+    for mr in &Tcb::MRS[2..2 + length] {
+        (*receiver_ptr).set_mr(*mr, sender_ref.get_mr(*mr));
+    }
+
+    if info.length() > MSG_REGISTERS &&
+        let Some(src) = sender_ref.ipc_buffer(false) &&
+        let Some(dst) = (*receiver_ptr).ipc_buffer(true)
+    {
+        length = info.length();
+        dst.msg[MSG_REGISTERS..length]
+            .copy_from_slice(&src.msg[MSG_REGISTERS..length]);
+    }
+
+    let info = MessageInfo::new(info.label(), 0, info.extra_caps(), length);
+    let info =
+        transfer_caps(info, sender_ref, &*receiver_ptr, endpoint, can_grant);
     (*receiver_ptr).set_mr(Tcb::MR1, badge);
+    (*receiver_ptr).set_mr(Tcb::MR2, info.word());
+}
+
+/// Send `fault` of `sender` to `receiver`, instead of a message.
+fn do_fault_transfer(
+    fault: Fault,
+    sender: &Tcb,
+    receiver: &mut Tcb,
+    badge: usize,
+) {
+    let words = fault.message(&sender.context);
+    for (mr, word) in Tcb::MRS[2..].iter().zip(words) {
+        receiver.set_mr(*mr, word);
+    }
+
+    let info = MessageInfo::new(fault.label(), 0, 0, MSG_REGISTERS);
+    receiver.set_mr(Tcb::MR1, badge);
+    receiver.set_mr(Tcb::MR2, info.word());
+}
+
+/// Apply the reply of fault handler `replier` to faulting `caller`.
+///
+/// A user exception handler may resume the thread elsewhere: the first
+/// reply words are its new RIP and RSP, non-canonical ones are ignored.
+/// Other faults, such as VM faults a pager resolved, restart the faulting
+/// instruction.
+fn do_fault_reply(fault: Fault, replier: &Tcb, caller: &mut Tcb) {
+    let info = MessageInfo::from_word(replier.get_mr(Tcb::MR2));
+    if let Fault::UserException { .. } = fault {
+        let canonical = |word: usize| VirtAddr::try_new(word as u64).is_ok();
+        let rip = replier.get_mr(Tcb::MR3);
+        if info.length() >= 1 && canonical(rip) {
+            caller.context.rip = rip;
+        }
+        let rsp = replier.get_mr(Tcb::MR4);
+        if info.length() >= 2 && canonical(rsp) {
+            caller.context.rsp = rsp;
+        }
+    }
+}
+
+/// Transfer extra capabilities of `info` from `sender` to `receiver`.
+///
+/// Endpoint capabilities to `endpoint` are unwrapped into their badge.
+/// Others need `can_grant` and are derived in the receive slot, so at most
+/// one gets through. Transfer stops at the first failure, the returned tag
+/// tells what was received.
+fn transfer_caps(
+    info: MessageInfo,
+    sender: &Tcb,
+    receiver: &Tcb,
+    endpoint: Option<PhysAddr>,
+    can_grant: bool,
+) -> MessageInfo {
+    let mut unwrapped = 0;
+    let mut count = 0;
+
+    if info.extra_caps() > 0 &&
+        let Ok(cspace) = sender.cspace() &&
+        let Some(src) = sender.ipc_buffer(false) &&
+        let Some(dst) = receiver.ipc_buffer(true)
+    {
+        let mut slot =
+            can_grant.then(|| receive_slot(receiver, dst)).flatten();
+
+        for i in 0..info.extra_caps() {
+            let Ok(cap) = cspace.lookup(src.caps_or_badges[i]) else {
+                break;
+            };
+
+            if let Ok(ep) = EndpointCap::try_from(cap) &&
+                endpoint == Some(ep.paddr())
+            {
+                dst.caps_or_badges[i] = ep.badge();
+                unwrapped |= 1 << i;
+            } else {
+                let Some(dst_slot) = slot.take() else {
+                    break;
+                };
+                if derive_cap(cap, dst_slot).is_err() {
+                    break;
+                }
+            }
+            count += 1;
+        }
+    }
+
+    MessageInfo::new(info.label(), unwrapped, count, info.length())
+}
 
-    // Transfer message registers.
-    for i in 0..4 {
-        let val = sender_ref.get_mr(Tcb::MR1 + i);
-        (*receiver_ptr).set_mr(Tcb::MR1 + i, val);
+/// Empty slot designated by `buffer` in `receiver` CSpace.
+fn receive_slot<'a>(
+    receiver: &'a Tcb,
+    buffer: &IpcBuffer,
+) -> Option<&'a CNodeEntry> {
+    let cnode = receiver.cspace().ok()?.lookup(buffer.receive_cnode).ok()?;
+    let slot = CSpace::new(cnode)
+        .ok()?
+        .lookup_with_depth(buffer.receive_index, buffer.receive_depth)
+        .ok()?;
+    slot.is_null().then_some(slot)
+}
+
+/// Derive `src` in empty `dst`, as its child in MDB.
+fn derive_cap(src: &CNodeEntry, dst: &CNodeEntry) -> Result<()> {
+    let mut raw = src.get();
+    match raw.cap_type {
+        ObjType::NullObj => return Err(SysError::SlotEmpty),
+        // Free memory is tracked per capability, a copy would reuse it.
+        ObjType::Untyped => return Err(SysError::UnableToDerive),
+        // Handlers are issued once per line.
+        ObjType::IrqControl => return Err(SysError::UnableToDerive),
+        // Copies would not share the ASID assigned later.
+        ObjType::VSpace if VSpaceCap::try_from(src)?.asid() == 0 => {
+            return Err(SysError::UnableToDerive);
+        },
+        _ => {},
     }
 
-    // TODO: Handle capability transfer if can_grant is true.
-    let _ = can_grant;
+    raw.mdb_prev = None;
+    raw.mdb_next = None;
+    dst.set(raw);
+    if let Ok(frame) = FrameCap::try_from(dst) {
+        frame.clear_mapped();
+    }
+    CNodeEntry::mdb_insert_after(src, dst);
+    Ok(())
 }
 
 /// Handle failed non-blocking receive.
@@ -139,6 +303,7 @@ unsafe fn do_nb_recv_failed_transfer(thread: NonNull<Tcb>) {
     let thread_ptr = thread.as_ptr();
     // Set badge to 0 to indicate no message.
     (*thread_ptr).set_mr(Tcb::MR1, 0);
+    (*thread_ptr).set_mr(Tcb::MR2, MessageInfo::default().word());
 }
 
 /// Send IPC message.
@@ -192,9 +357,13 @@ pub unsafe fn send_ipc(
                 ep.state = EndpointState::Idle;
             }
 
-            do_ipc_transfer(sender, receiver, badge, can_grant);
-
-            let receiver_ptr = receiver.as_ptr();
+            do_ipc_transfer(
+                sender,
+                receiver,
+                Some(endpoint.paddr()),
+                badge,
+                can_grant,
+            );
 
             if do_call {
                 if can_grant || can_grant_reply {
@@ -205,19 +374,43 @@ pub unsafe fn send_ipc(
                 }
             }
 
-            (*receiver_ptr).state = ThreadState::Running;
-
-            // Schedule receiver (preempt current thread).
-            if let Some(sched) = SCHEDULER.get() {
-                (*receiver_ptr).state = ThreadState::Inactive;
-                let _ = sched.get_mut().wake(receiver);
-            }
+            // Receiver is picked up on next schedule of its core.
+            let _ = scheduler::wake(receiver);
 
             Ok(())
         },
     }
 }
 
+/// Send `fault` of `thread` to its fault endpoint, as a call.
+///
+/// Without a fault endpoint the thread stays inactive, keeping its fault.
+///
+/// # Safety
+/// TCB pointer must be valid.
+pub unsafe fn send_fault_ipc(
+    thread: NonNull<Tcb>,
+    fault: Fault,
+) -> Result<()> {
+    let thread_ptr = thread.as_ptr();
+    (*thread_ptr).set_fault(Some(fault));
+
+    let Some(endpoint) = (*thread_ptr).fault_ep() else {
+        (*thread_ptr).state = ThreadState::Inactive;
+        return Err(SysError::SlotEmpty);
+    };
+
+    send_ipc(
+        true,
+        true,
+        endpoint.badge(),
+        endpoint.can_grant(),
+        endpoint.can_grant_reply(),
+        thread,
+        &endpoint,
+    )
+}
+
 /// Receive IPC message.
 ///
 /// # Safety
@@ -230,7 +423,15 @@ pub unsafe fn receive_ipc(
     let ep = endpoint.as_object_mut();
     let receiver_ptr = receiver.as_ptr();
 
-    // TODO: Check for bound notification first (like seL4 does).
+    // Pending signals on bound notification come first.
+    if let Some(ntfn) = (*receiver_ptr).notification &&
+        (*ntfn.as_ptr()).state() == NotificationState::Active
+    {
+        (*ntfn.as_ptr()).wait(receiver, false);
+        // Empty message, unlike any endpoint one.
+        (*receiver_ptr).set_mr(Tcb::MR2, MessageInfo::default().word());
+        return Ok(());
+    }
 
     match ep.state {
         EndpointState::Idle | EndpointState::Recv => {
@@ -271,6 +472,7 @@ pub unsafe fn receive_ipc(
             do_ipc_transfer(
                 sender,
                 receiver,
+                Some(endpoint.paddr()),
                 ipc_state.badge,
                 ipc_state.can_grant,
             );
@@ -283,10 +485,7 @@ pub unsafe fn receive_ipc(
                     (*sender_ptr).state = ThreadState::Inactive;
                 }
             } else {
-                (*sender_ptr).state = ThreadState::Inactive;
-                if let Some(sched) = SCHEDULER.get() {
-                    let _ = sched.get_mut().wake(sender);
-                }
+                let _ = scheduler::wake(sender);
             }
 
             Ok(())
@@ -315,16 +514,48 @@ unsafe fn setup_caller_cap(
     Ok(())
 }
 
+/// Reply to the thread blocked in a call on `replier`.
+///
+/// Message registers are transferred without badge and the caller is
+/// woken up. A faulting caller gets its fault cleared instead, see
+/// [`do_fault_reply`]. Nothing happens if there is no caller.
+///
+/// # Safety
+/// TCB pointer must be valid.
+pub unsafe fn reply_ipc(replier: NonNull<Tcb>) -> Result<()> {
+    let replier_ptr = replier.as_ptr();
+    let Some(caller) = (*replier_ptr).caller.take() else {
+        return Ok(());
+    };
+    let caller_ptr = caller.as_ptr();
+
+    if (*caller_ptr).state != ThreadState::BlockedOnReply {
+        return Ok(());
+    }
+
+    match (*caller_ptr).fault() {
+        Some(fault) => {
+            do_fault_reply(fault, replier.as_ref(), &mut *caller_ptr);
+            (*caller_ptr).set_fault(None);
+        },
+        None => do_ipc_transfer(replier, caller, None, 0, false),
+    }
+    (*caller_ptr).reply_to = None;
+
+    let _ = scheduler::wake(caller);
+    Ok(())
+}
+
 /// Reply from kernel with error.
 pub fn reply_from_kernel_error(thread: &mut Tcb, error_type: usize) {
     thread.set_mr(Tcb::MR1, 0);
-    thread.set_mr(Tcb::MR2, error_type);
+    thread.set_mr(Tcb::MR2, MessageInfo::new(error_type, 0, 0, 0).word());
 }
 
 /// Reply from kernel with success (empty message).
 pub fn reply_from_kernel_success_empty(thread: &mut Tcb) {
     thread.set_mr(Tcb::MR1, 0);
-    // Message info with 0 length, 0 caps, 0 extra caps, label = 0.
+    thread.set_mr(Tcb::MR2, MessageInfo::default().word());
 }
 
 /// Cancel IPC for a thread.
@@ -354,8 +585,13 @@ pub unsafe fn cancel_ipc(tcb: NonNull<Tcb>) {
         },
 
         ThreadState::BlockedOnNotification => {
-            // TODO: Cancel notification signal.
+            if let Some(ntfn_ptr) = (*tcb_ptr).blocking_object {
+                let ntfn = &mut *(ntfn_ptr.as_ptr() as *mut NotificationObj);
+                ntfn.cancel(tcb);
+            }
+
             (*tcb_ptr).state = ThreadState::Inactive;
+            (*tcb_ptr).blocking_object = None;
         },
 
         ThreadState::BlockedOnReply => {
@@ -388,14 +624,11 @@ pub unsafe fn cancel_all_ipc(endpoint: &EndpointCap<'_>) {
     while let Some(tcb) = ep.queue.dequeue_head() {
         let tcb_ptr = tcb.as_ptr();
 
-        (*tcb_ptr).state = ThreadState::Restart;
         (*tcb_ptr).blocking_object = None;
 
         // Re-enqueue in scheduler.
-        if let Some(sched) = SCHEDULER.get() {
-            (*tcb_ptr).state = ThreadState::Inactive;
-            let _ = sched.get_mut().enqueue(tcb);
-        }
+        (*tcb_ptr).state = ThreadState::Inactive;
+        let _ = scheduler::enqueue(tcb);
     }
 
     ep.state = EndpointState::Idle;
@@ -424,14 +657,11 @@ pub unsafe fn cancel_badged_sends(endpoint: &EndpointCap<'_>, badge: usize) {
             // Restart this thread.
             (*tcb_ptr).ep_next = None;
             (*tcb_ptr).ep_prev = None;
-            (*tcb_ptr).state = ThreadState::Restart;
             (*tcb_ptr).blocking_object = None;
 
             // Re-enqueue in scheduler.
-            if let Some(sched) = SCHEDULER.get() {
-                (*tcb_ptr).state = ThreadState::Inactive;
-                let _ = sched.get_mut().enqueue(tcb);
-            }
+            (*tcb_ptr).state = ThreadState::Inactive;
+            let _ = scheduler::enqueue(tcb);
         } else {
             // Keep in queue.
             new_queue.append(tcb);
@@ -446,3 +676,328 @@ pub unsafe fn cancel_badged_sends(endpoint: &EndpointCap<'_>, badge: usize) {
         ep.state = EndpointState::Idle;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use x86_64::instructions::interrupts::without_interrupts;
+
+    use super::*;
+    use crate::arch::trapframe::TrapFrame;
+    use crate::arch::vspace::kernel_paddr;
+    use crate::arch::{VirtAddr, cpuid, ipi};
+    use crate::objects::cnode::{CNODE_DEPTH, CNodeCap};
+    use crate::objects::frame::FrameSize;
+    use crate::scheduler::{MAX_CPUS, lock};
+    use crate::testing::{NODE_RADIX, Node, Page, running};
+
+    /// Endpoint that never crosses a page.
+    #[repr(C, align(32))]
+    struct Endpoint(EndpointObj);
+
+    fn endpoint_cap(endpoint: &Endpoint, badge: usize) -> CNodeEntry {
+        let paddr = kernel_paddr(VirtAddr::from_ptr(endpoint)).unwrap();
+        let entry = CNodeEntry::new();
+        entry.set(EndpointCap::mint(
+            paddr.as_u64() as usize,
+            badge,
+            CapRights::all(),
+        ));
+        entry
+    }
+
+    fn frame_cap(page: &Page) -> CNodeEntry {
+        let paddr = page.paddr();
+        let entry = CNodeEntry::new();
+        entry.set(FrameCap::mint(
+            paddr.as_u64() as usize,
+            FrameSize::Small,
+            false,
+            CapRights::READ | CapRights::WRITE,
+        ));
+        FrameCap::try_from(&entry)
+            .unwrap()
+            .set_mapped(1, 0x1000)
+            .unwrap();
+        entry
+    }
+
+    #[test_case]
+    fn call_waits_for_receiver() {
+        let endpoint = Endpoint(EndpointObj::new());
+        let entry = endpoint_cap(&endpoint, 7);
+        let cap = EndpointCap::try_from(&entry).unwrap();
+
+        let mut caller = running();
+        let mut callee = running();
+        caller.set_mr(Tcb::MR2, MessageInfo::new(3, 0, 0, 1).word());
+        caller.set_mr(Tcb::MR3, 42);
+        caller.set_mr(Tcb::MR4, 43);
+        let caller_ptr = NonNull::from(&mut caller);
+        let callee_ptr = NonNull::from(&mut callee);
+
+        unsafe {
+            send_ipc(true, true, cap.badge(), true, true, caller_ptr, &cap)
+                .unwrap();
+            assert_eq!(caller_ptr.as_ref().state, ThreadState::BlockedOnSend);
+            assert_eq!(cap.as_object().state(), EndpointState::Send);
+
+            receive_ipc(callee_ptr, &cap, true).unwrap();
+            assert_eq!(cap.as_object().state(), EndpointState::Idle);
+            assert_eq!(caller_ptr.as_ref().state, ThreadState::BlockedOnReply);
+        }
+
+        assert_eq!(callee.get_mr(Tcb::MR1), 7);
+        assert_eq!(
+            MessageInfo::from_word(callee.get_mr(Tcb::MR2)),
+            MessageInfo::new(3, 0, 0, 1)
+        );
+        assert_eq!(callee.get_mr(Tcb::MR3), 42);
+        assert_eq!(callee.get_mr(Tcb::MR4), 0);
+        assert_eq!(callee.caller, Some(caller_ptr));
+    }
+
+    #[test_case]
+    fn receive_on_other_core() {
+        let Some(cpu) =
+            (0..MAX_CPUS).find(|&cpu| cpu != cpuid() && ipi::is_online(cpu))
+        else {
+            return;
+        };
+        let endpoint = Endpoint(EndpointObj::new());
+        let entry = endpoint_cap(&endpoint, 7);
+        let cap = EndpointCap::try_from(&entry).unwrap();
+
+        // Without user mappings, the receiver faults as soon as its core
+        // switches to it, and stops there.
+        let mut sender = running();
+        let mut receiver = running();
+        receiver.affinity = cpu;
+        receiver.context = TrapFrame::user(0, 0);
+        sender.set_mr(Tcb::MR2, MessageInfo::new(3, 0, 0, 1).word());
+        sender.set_mr(Tcb::MR3, 42);
+        let receiver = NonNull::from(&mut receiver);
+
+        // As the syscalls of both threads would, the timer of this core
+        // must not try to take the lock meanwhile.
+        without_interrupts(|| unsafe {
+            let _lock = lock::acquire();
+            receive_ipc(receiver, &cap, true).unwrap();
+            let sender = NonNull::from(&mut sender);
+            send_ipc(true, false, cap.badge(), true, true, sender, &cap)
+                .unwrap();
+        });
+
+        let fault = loop {
+            let fault = without_interrupts(|| {
+                let _lock = lock::acquire();
+                // SAFETY: the receiver core writes it under the lock.
+                unsafe { receiver.as_ref() }.fault()
+            });
+            if let Some(fault) = fault {
+                break fault;
+            }
+            core::hint::spin_loop();
+        };
+        assert!(matches!(fault, Fault::VmFault { addr: 0, .. }));
+
+        let receiver = unsafe { receiver.as_ref() };
+        assert_eq!(receiver.state, ThreadState::Inactive);
+        assert_eq!(receiver.get_mr(Tcb::MR1), 7);
+        assert_eq!(receiver.get_mr(Tcb::MR3), 42);
+    }
+
+    #[test_case]
+    fn bound_signal_received_as_empty_message() {
+        let endpoint = Endpoint(EndpointObj::new());
+        let entry = endpoint_cap(&endpoint, 0);
+        let cap = EndpointCap::try_from(&entry).unwrap();
+        let mut ntfn = NotificationObj::new();
+
+        let mut receiver = running();
+        receiver.set_mr(Tcb::MR2, MessageInfo::new(3, 0, 0, 1).word());
+        let receiver_ptr = NonNull::from(&mut receiver);
+
+        unsafe {
+            ntfn.bind(receiver_ptr).unwrap();
+            ntfn.signal(0b100);
+            receive_ipc(receiver_ptr, &cap, true).unwrap();
+            ntfn.unbind();
+        }
+
+        assert_eq!(receiver.state, ThreadState::Running);
+        assert_eq!(receiver.get_mr(Tcb::MR1), 0b100);
+        assert_eq!(receiver.get_mr(Tcb::MR2), MessageInfo::default().word());
+    }
+
+    #[test_case]
+    fn long_message_through_ipc_buffers() {
+        let endpoint = Endpoint(EndpointObj::new());
+        let entry = endpoint_cap(&endpoint, 0);
+        let cap = EndpointCap::try_from(&entry).unwrap();
+
+        let pages = [const { Page::new() }; 2];
+        let frames = [frame_cap(&pages[0]), frame_cap(&pages[1])];
+        let mut caller = running();
+        let mut callee = running();
+        caller
+            .set_ipc_buffer(&FrameCap::try_from(&frames[0]).unwrap())
+            .unwrap();
+        callee
+            .set_ipc_buffer(&FrameCap::try_from(&frames[1]).unwrap())
+            .unwrap();
+
+        caller.set_mr(Tcb::MR2, MessageInfo::new(0, 0, 0, 6).word());
+        caller.set_mr(Tcb::MR6, 3);
+        caller.ipc_buffer(false).unwrap().msg[5] = 5;
+
+        unsafe {
+            let caller_ptr = NonNull::from(&mut caller);
+            send_ipc(true, true, 0, true, true, caller_ptr, &cap).unwrap();
+            receive_ipc(NonNull::from(&mut callee), &cap, true).unwrap();
+        }
+
+        let info = MessageInfo::from_word(callee.get_mr(Tcb::MR2));
+        assert_eq!(info.length(), 6);
+        assert_eq!(callee.get_mr(Tcb::MR6), 3);
+        assert_eq!(callee.ipc_buffer(false).unwrap().msg[5], 5);
+    }
+
+    #[test_case]
+    fn extra_caps_granted_or_unwrapped() {
+        let endpoint = Endpoint(EndpointObj::new());
+        let entry = endpoint_cap(&endpoint, 0);
+        let cap = EndpointCap::try_from(&entry).unwrap();
+
+        // Both threads share a CNode: slot 1 holds a frame, slot 2 a badged
+        // endpoint and slot 3 the CNode itself.
+        let node = Node::new();
+        let cnode = CNodeCap::mint(
+            node.paddr(),
+            NODE_RADIX,
+            CNODE_DEPTH - NODE_RADIX,
+            0,
+            CapRights::all(),
+        );
+        let page = Page::new();
+        node.0[1].set(frame_cap(&page).get());
+        node.0[2].set(endpoint_cap(&endpoint, 9).get());
+        node.0[3].set(cnode);
+
+        let pages = [const { Page::new() }; 2];
+        let frames = [frame_cap(&pages[0]), frame_cap(&pages[1])];
+        let mut caller = running();
+        let mut callee = running();
+        for (tcb, frame) in
+            [(&mut caller, &frames[0]), (&mut callee, &frames[1])]
+        {
+            tcb.cspace_root.set(cnode);
+            tcb.set_ipc_buffer(&FrameCap::try_from(frame).unwrap())
+                .unwrap();
+        }
+
+        caller.set_mr(Tcb::MR2, MessageInfo::new(0, 0, 2, 0).word());
+        caller.ipc_buffer(false).unwrap().caps_or_badges[..2]
+            .copy_from_slice(&[2, 1]);
+        let buffer = callee.ipc_buffer(true).unwrap();
+        buffer.receive_cnode = 3;
+        buffer.receive_index = 5;
+        buffer.receive_depth = CNODE_DEPTH;
+
+        unsafe {
+            let caller_ptr = NonNull::from(&mut caller);
+            send_ipc(true, true, 0, true, true, caller_ptr, &cap).unwrap();
+            receive_ipc(NonNull::from(&mut callee), &cap, true).unwrap();
+        }
+
+        let info = MessageInfo::from_word(callee.get_mr(Tcb::MR2));
+        assert_eq!(info.extra_caps(), 2);
+        assert_eq!(info.caps_unwrapped(), 0b01);
+        assert_eq!(callee.ipc_buffer(false).unwrap().caps_or_badges[0], 9);
+
+        let granted = FrameCap::try_from(&node.0[5]).unwrap();
+        assert_eq!(
+            granted.paddr(),
+            FrameCap::try_from(&node.0[1]).unwrap().paddr()
+        );
+        assert!(!granted.is_mapped());
+    }
+
+    #[test_case]
+    fn user_exception_sent_to_fault_handler() {
+        let endpoint = Endpoint(EndpointObj::new());
+        let entry = endpoint_cap(&endpoint, 5);
+        let cap = EndpointCap::try_from(&entry).unwrap();
+
+        let mut thread = running();
+        let mut handler = running();
+        thread.set_fault_ep(&cap).unwrap();
+        thread.context.rip = 0x40_1000;
+        thread.context.rsp = 0x7f_f000;
+        let fault = Fault::UserException {
+            number: 13,
+            code: 0x10,
+        };
+
+        unsafe {
+            let thread_ptr = NonNull::from(&mut thread);
+            send_fault_ipc(thread_ptr, fault).unwrap();
+            assert_eq!(thread_ptr.as_ref().state, ThreadState::BlockedOnSend);
+            receive_ipc(NonNull::from(&mut handler), &cap, true).unwrap();
+            assert_eq!(thread_ptr.as_ref().state, ThreadState::BlockedOnReply);
+        }
+
+        let info = MessageInfo::from_word(handler.get_mr(Tcb::MR2));
+        assert_eq!(info.label(), fault.label());
+        assert_eq!(handler.get_mr(Tcb::MR1), 5);
+        assert_eq!(
+            [Tcb::MR3, Tcb::MR4, Tcb::MR5, Tcb::MR6]
+                .map(|mr| handler.get_mr(mr)),
+            [0x40_1000, 0x7f_f000, 13, 0x10]
+        );
+
+        handler.set_mr(Tcb::MR2, MessageInfo::new(0, 0, 0, 1).word());
+        handler.set_mr(Tcb::MR3, 0x40_2000);
+        do_fault_reply(fault, &handler, &mut thread);
+        assert_eq!({ thread.context.rip }, 0x40_2000);
+        assert_eq!({ thread.context.rsp }, 0x7f_f000);
+    }
+
+    #[test_case]
+    fn vm_fault_sent_to_pager() {
+        let endpoint = Endpoint(EndpointObj::new());
+        let entry = endpoint_cap(&endpoint, 5);
+        let cap = EndpointCap::try_from(&entry).unwrap();
+
+        let mut thread = running();
+        let mut pager = running();
+        thread.set_fault_ep(&cap).unwrap();
+        thread.context.rip = 0x40_1000;
+        thread.context.rsp = 0x7f_f000;
+        let fault = Fault::VmFault {
+            addr: 0x50_0008,
+            fsr: 0x6,
+            instruction_fault: false,
+        };
+
+        unsafe {
+            send_fault_ipc(NonNull::from(&mut thread), fault).unwrap();
+            receive_ipc(NonNull::from(&mut pager), &cap, true).unwrap();
+        }
+
+        let info = MessageInfo::from_word(pager.get_mr(Tcb::MR2));
+        assert_eq!(info.label(), 7);
+        assert_eq!(
+            [Tcb::MR3, Tcb::MR4, Tcb::MR5, Tcb::MR6]
+                .map(|mr| pager.get_mr(mr)),
+            [0x40_1000, 0x50_0008, 0, 0x6]
+        );
+
+        // Once mapped, the faulting instruction runs again.
+        pager.set_mr(Tcb::MR2, MessageInfo::new(0, 0, 0, 2).word());
+        pager.set_mr(Tcb::MR3, 0x40_2000);
+        pager.set_mr(Tcb::MR4, 0x7f_e000);
+        do_fault_reply(fault, &pager, &mut thread);
+        assert_eq!({ thread.context.rip }, 0x40_1000);
+        assert_eq!({ thread.context.rsp }, 0x7f_f000);
+    }
+}
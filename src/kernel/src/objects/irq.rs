@@ -0,0 +1,246 @@
+//! Interrupt capabilities.
+//!
+//! The IRQ control capability issues one handler capability per GSI. Once a
+//! handler has a notification, the kernel masks its line and signals the
+//! notification when the interrupt fires. The line stays masked until user
+//! code acks the handler.
+//!
+//! Deleting the last handler capability masks its line, which can then be
+//! issued again. Deleting the last capability to a notification detaches it
+//! from the lines signalling it.
+
+use core::ptr::NonNull;
+
+use spin::Mutex;
+
+use crate::APIC;
+use crate::arch::constants::interrupts::MAX_IRQS;
+use crate::error::{Result, SysError};
+use crate::objects::cnode::CNodeEntry;
+use crate::objects::notification::{NotificationCap, NotificationObj};
+use crate::objects::{CapRaw, CapRef, CapRights, ObjType};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IrqState {
+    /// No handler issued.
+    #[default]
+    Inactive,
+    /// Line used by the kernel itself.
+    Reserved,
+    /// Handler issued, interrupts signal its notification.
+    Signal,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct IrqEntry {
+    state: IrqState,
+    notification: Option<NonNull<NotificationObj>>,
+    badge: usize,
+}
+
+impl IrqEntry {
+    const INACTIVE: Self = Self {
+        state: IrqState::Inactive,
+        notification: None,
+        badge: 0,
+    };
+}
+
+struct IrqTable([IrqEntry; MAX_IRQS]);
+
+// SAFETY: notifications are only reached with the table locked.
+unsafe impl Send for IrqTable {}
+
+static IRQS: Mutex<IrqTable> =
+    Mutex::new(IrqTable([IrqEntry::INACTIVE; MAX_IRQS]));
+
+/// Keep `gsi` for the kernel, no handler can be issued for it.
+pub fn reserve(gsi: usize) {
+    if let Some(entry) = IRQS.lock().0.get_mut(gsi) {
+        *entry = IrqEntry {
+            state: IrqState::Reserved,
+            ..IrqEntry::INACTIVE
+        };
+    }
+}
+
+/// State of `gsi` line.
+pub fn state(gsi: usize) -> Option<IrqState> {
+    IRQS.lock().0.get(gsi).map(|entry| entry.state)
+}
+
+/// Mask `gsi` and free it for a new handler, once its last handler
+/// capability is deleted.
+pub fn release(gsi: usize) {
+    APIC.lock().ioapic_mask(gsi as u32, true);
+    if let Some(entry) = IRQS.lock().0.get_mut(gsi) &&
+        entry.state == IrqState::Signal
+    {
+        *entry = IrqEntry::INACTIVE;
+    }
+}
+
+/// Mask the lines signalling `ntfn`, once its last capability is deleted.
+pub fn detach(ntfn: &NotificationCap) {
+    // SAFETY: only the pointer is compared.
+    let ntfn = NonNull::from(unsafe { ntfn.as_object_mut() });
+    let mut irqs = IRQS.lock();
+    let entries = irqs.0.iter_mut().enumerate();
+    for (gsi, entry) in entries.filter(|(_, e)| e.notification == Some(ntfn)) {
+        APIC.lock().ioapic_mask(gsi as u32, true);
+        entry.notification = None;
+        entry.badge = 0;
+    }
+}
+
+/// Forward interrupt of masked line `gsi` to its notification.
+///
+/// # Safety
+/// Notifications set on handlers must be valid.
+pub unsafe fn handle(gsi: usize) {
+    let irqs = IRQS.lock();
+    match irqs.0.get(gsi) {
+        Some(IrqEntry {
+            state: IrqState::Signal,
+            notification: Some(ntfn),
+            badge,
+        }) => (*ntfn.as_ptr()).signal(*badge),
+        _ => log::warn!("spurious interrupt on GSI {gsi}"),
+    }
+}
+
+#[derive(Debug)]
+pub enum IrqControlObj {}
+
+pub type IrqControlCap<'a> = CapRef<'a, IrqControlObj>;
+
+impl IrqControlCap<'_> {
+    /// Create the IRQ control capability.
+    pub const fn mint() -> CapRaw {
+        let mut capraw = CapRaw::default_with_type(ObjType::IrqControl);
+        capraw.rights = CapRights::all();
+        capraw
+    }
+
+    /// Issue the handler of `gsi` in empty slot `dst`.
+    pub fn issue(&self, gsi: usize, dst: &CNodeEntry) -> Result<()> {
+        if !dst.is_null() {
+            return Err(SysError::SlotNotEmpty);
+        }
+
+        let mut irqs = IRQS.lock();
+        let entry = irqs.0.get_mut(gsi).ok_or(SysError::RangeError)?;
+        if entry.state != IrqState::Inactive {
+            return Err(SysError::InvalidOperation);
+        }
+        entry.state = IrqState::Signal;
+
+        dst.set(IrqHandlerCap::mint(gsi));
+        CNodeEntry::mdb_insert_after(self.raw, dst);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum IrqHandlerObj {}
+
+pub type IrqHandlerCap<'a> = CapRef<'a, IrqHandlerObj>;
+
+impl IrqHandlerCap<'_> {
+    /// Create a handler capability for `gsi`.
+    pub const fn mint(gsi: usize) -> CapRaw {
+        let mut capraw = CapRaw::default_with_type(ObjType::Interrupt);
+        capraw.arg1 = gsi;
+        capraw.rights = CapRights::all();
+        capraw
+    }
+
+    pub fn gsi(&self) -> usize {
+        self.raw.get().arg1
+    }
+
+    /// Signal `ntfn` with its badge on interrupt, and unmask the line.
+    pub fn set_notification(&self, ntfn: &NotificationCap) -> Result<()> {
+        if !ntfn.can_send() {
+            return Err(SysError::InvalidOperation);
+        }
+
+        {
+            let mut irqs = IRQS.lock();
+            let entry = &mut irqs.0[self.gsi()];
+            // SAFETY: only the pointer is kept, used under the table lock.
+            entry.notification =
+                Some(NonNull::from(unsafe { ntfn.as_object_mut() }));
+            entry.badge = ntfn.badge();
+        }
+
+        self.ack();
+        Ok(())
+    }
+
+    /// Unmask the line once its last interrupt is handled.
+    pub fn ack(&self) {
+        APIC.lock().ioapic_mask(self.gsi() as u32, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Page;
+
+    #[test_case]
+    fn issue_handler_once() {
+        // Last line, unused by the kernel and tests devices.
+        let gsi = MAX_IRQS - 1;
+        let control = CNodeEntry::new();
+        let first = CNodeEntry::new();
+        let second = CNodeEntry::new();
+        control.set(IrqControlCap::mint());
+
+        let control = IrqControlCap::try_from(&control).unwrap();
+        control.issue(gsi, &first).unwrap();
+        assert_eq!(IrqHandlerCap::try_from(&first).unwrap().gsi(), gsi);
+        assert_eq!(state(gsi), Some(IrqState::Signal));
+
+        assert_eq!(
+            control.issue(gsi, &second).unwrap_err(),
+            SysError::InvalidOperation
+        );
+        assert_eq!(
+            control.issue(MAX_IRQS, &second).unwrap_err(),
+            SysError::RangeError
+        );
+
+        // Leave the line free for later tests.
+        first.delete();
+    }
+
+    #[test_case]
+    fn delete_frees_line() {
+        // Last line, unused by the kernel and tests devices.
+        let gsi = MAX_IRQS - 1;
+        let page = Page::new();
+        let [control, handler, ntfn] = [const { CNodeEntry::new() }; 3];
+        control.set(IrqControlCap::mint());
+        let paddr = page.paddr().as_u64() as usize;
+        ntfn.set(NotificationCap::mint(paddr, 1, CapRights::all()));
+
+        let control = IrqControlCap::try_from(&control).unwrap();
+        control.issue(gsi, &handler).unwrap();
+        IrqHandlerCap::try_from(&handler)
+            .unwrap()
+            .set_notification(&NotificationCap::try_from(&ntfn).unwrap())
+            .unwrap();
+
+        // The handler outlives its notification.
+        ntfn.delete();
+        assert!(IRQS.lock().0[gsi].notification.is_none());
+        assert_eq!(state(gsi), Some(IrqState::Signal));
+
+        handler.delete();
+        assert_eq!(state(gsi), Some(IrqState::Inactive));
+        control.issue(gsi, &handler).unwrap();
+        handler.delete();
+    }
+}
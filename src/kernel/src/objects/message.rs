@@ -0,0 +1,136 @@
+//! IPC message layout.
+
+use crate::mask;
+use crate::vspace::PAGE_SIZE_4K;
+
+/// Number of message words passed in registers, after badge and tag.
+pub const MSG_REGISTERS: usize = 4;
+
+/// Maximum message length, in words.
+pub const MSG_MAX_LENGTH: usize = 120;
+
+/// Maximum number of extra capabilities in a message.
+pub const MSG_MAX_EXTRA_CAPS: usize = 3;
+
+/// Thread IPC buffer, lays in a 4 KiB frame shared with user.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct IpcBuffer {
+    /// Message tag, unused by the kernel.
+    pub tag: usize,
+    /// Message words, the first [`MSG_REGISTERS`] ones travel in registers.
+    pub msg: [usize; MSG_MAX_LENGTH],
+    /// Free for user.
+    pub user_data: usize,
+    /// Extra capabilities to send, or badges of unwrapped received ones.
+    pub caps_or_badges: [usize; MSG_MAX_EXTRA_CAPS],
+    /// CNode receiving capabilities.
+    pub receive_cnode: usize,
+    /// Slot receiving capabilities in `receive_cnode`.
+    pub receive_index: usize,
+    /// Bits to resolve `receive_index`.
+    pub receive_depth: usize,
+}
+
+const _: () = assert!(size_of::<IpcBuffer>() <= PAGE_SIZE_4K);
+
+/// Message tag, packed in one word.
+///
+/// From low to high bits: length (7), extra caps (2), caps unwrapped (3)
+/// and label (remaining bits).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct MessageInfo(usize);
+
+impl MessageInfo {
+    const EXTRA_CAPS_BITS: usize = 2;
+    const EXTRA_CAPS_OFFSET: usize = Self::LENGTH_BITS;
+    const LABEL_OFFSET: usize = Self::UNWRAPPED_OFFSET + Self::UNWRAPPED_BITS;
+    const LENGTH_BITS: usize = 7;
+    const UNWRAPPED_BITS: usize = 3;
+    const UNWRAPPED_OFFSET: usize =
+        Self::EXTRA_CAPS_OFFSET + Self::EXTRA_CAPS_BITS;
+
+    /// Pack a message tag, out of range fields are truncated.
+    pub const fn new(
+        label: usize,
+        caps_unwrapped: usize,
+        extra_caps: usize,
+        length: usize,
+    ) -> Self {
+        Self(
+            (label << Self::LABEL_OFFSET) |
+                ((caps_unwrapped & mask!(Self::UNWRAPPED_BITS)) <<
+                    Self::UNWRAPPED_OFFSET) |
+                ((extra_caps & mask!(Self::EXTRA_CAPS_BITS)) <<
+                    Self::EXTRA_CAPS_OFFSET) |
+                (length & mask!(Self::LENGTH_BITS)),
+        )
+    }
+
+    /// Read a tag from a message register, clamping its length and extra
+    /// capabilities.
+    pub const fn from_word(word: usize) -> Self {
+        let info = Self(word);
+        let length = if info.length() > MSG_MAX_LENGTH {
+            MSG_MAX_LENGTH
+        } else {
+            info.length()
+        };
+        let extra_caps = if info.extra_caps() > MSG_MAX_EXTRA_CAPS {
+            MSG_MAX_EXTRA_CAPS
+        } else {
+            info.extra_caps()
+        };
+        Self::new(info.label(), info.caps_unwrapped(), extra_caps, length)
+    }
+
+    /// Packed tag.
+    pub const fn word(self) -> usize {
+        self.0
+    }
+
+    /// User defined label.
+    pub const fn label(self) -> usize {
+        self.0 >> Self::LABEL_OFFSET
+    }
+
+    /// Bitmask of extra capabilities unwrapped into badges.
+    pub const fn caps_unwrapped(self) -> usize {
+        (self.0 >> Self::UNWRAPPED_OFFSET) & mask!(Self::UNWRAPPED_BITS)
+    }
+
+    /// Number of extra capabilities.
+    pub const fn extra_caps(self) -> usize {
+        (self.0 >> Self::EXTRA_CAPS_OFFSET) & mask!(Self::EXTRA_CAPS_BITS)
+    }
+
+    /// Number of message words.
+    pub const fn length(self) -> usize {
+        self.0 & mask!(Self::LENGTH_BITS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn message_info_fields() {
+        let info = MessageInfo::new(0xbeef, 0b101, 2, 42);
+        assert_eq!(info.label(), 0xbeef);
+        assert_eq!(info.caps_unwrapped(), 0b101);
+        assert_eq!(info.extra_caps(), 2);
+        assert_eq!(info.length(), 42);
+        assert_eq!(MessageInfo::from_word(info.word()), info);
+    }
+
+    #[test_case]
+    fn message_info_clamped() {
+        let info =
+            MessageInfo::from_word(MessageInfo::new(1, 0, 3, 127).word());
+        assert_eq!(info.length(), MSG_MAX_LENGTH);
+        assert_eq!(info.extra_caps(), MSG_MAX_EXTRA_CAPS);
+        assert_eq!(info.label(), 1);
+    }
+}
@@ -1,8 +1,12 @@
 //! seL4-like capabilities objects.
 
+pub mod asid;
 pub mod cnode;
 pub mod endpoint;
 pub mod frame;
+pub mod irq;
+pub mod message;
+pub mod notification;
 pub mod nullcap;
 pub mod tcb;
 pub mod traits;
@@ -34,6 +38,10 @@ pub enum ObjType {
     Monitor = 7,
     Interrupt = 8,
     VSpace = 9,
+    Notification = 10,
+    IrqControl = 11,
+    AsidControl = 12,
+    AsidPool = 13,
 }
 
 bitflags::bitflags! {
@@ -47,6 +55,7 @@ bitflags::bitflags! {
         const CONTROL = 0b0001_0000;
         const SEND    = 0b0010_0000;
         const RECEIVE = 0b0100_0000;
+        const GRANT_REPLY = 0b1000_0000;
     }
 }
 
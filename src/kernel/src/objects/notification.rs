@@ -0,0 +1,291 @@
+//! Notification objects for asynchronous signalling.
+
+use core::ptr::NonNull;
+
+use crate::error::{Result, SysError};
+use crate::objects::endpoint::cancel_ipc;
+use crate::objects::message::MessageInfo;
+use crate::objects::tcb::{Tcb, TcbQueue, ThreadState};
+use crate::objects::{CapRaw, CapRef, CapRights, ObjType};
+use crate::scheduler;
+use crate::vspace::phys_to_virt;
+
+/// Notification object size, in bits.
+pub const NOTIFICATION_BIT_SZ: usize = 6;
+
+const _: () =
+    assert!(size_of::<NotificationObj>() <= 1 << NOTIFICATION_BIT_SZ);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum NotificationState {
+    /// No pending signal, nobody waiting.
+    #[default]
+    Idle = 0,
+    /// Threads wait in queue.
+    Waiting = 1,
+    /// Signals are pending in word.
+    Active = 2,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct NotificationObj {
+    state: NotificationState,
+    /// Badges of pending signals, OR-ed together.
+    word: usize,
+    queue: TcbQueue,
+    /// Thread also woken up while it waits on an endpoint.
+    bound_tcb: Option<NonNull<Tcb>>,
+}
+
+impl NotificationObj {
+    /// Create a new idle notification.
+    pub const fn new() -> Self {
+        Self {
+            state: NotificationState::Idle,
+            word: 0,
+            queue: TcbQueue::new(),
+            bound_tcb: None,
+        }
+    }
+
+    pub fn state(&self) -> NotificationState {
+        self.state
+    }
+
+    pub fn bound_tcb(&self) -> Option<NonNull<Tcb>> {
+        self.bound_tcb
+    }
+
+    /// Signal `badge`.
+    ///
+    /// The first waiting thread receives it, or the bound thread if it
+    /// waits on an endpoint. Otherwise `badge` stays pending.
+    ///
+    /// # Safety
+    /// Queued and bound TCBs must be valid.
+    pub unsafe fn signal(&mut self, badge: usize) {
+        match self.state {
+            NotificationState::Idle => match self.bound_tcb {
+                Some(tcb)
+                    if tcb.as_ref().state == ThreadState::BlockedOnReceive =>
+                {
+                    cancel_ipc(tcb);
+                    (*tcb.as_ptr()).set_mr(Tcb::MR1, badge);
+                    // Empty message, unlike any endpoint one.
+                    (*tcb.as_ptr())
+                        .set_mr(Tcb::MR2, MessageInfo::default().word());
+                    let _ = scheduler::enqueue(tcb);
+                },
+                _ => {
+                    self.word = badge;
+                    self.state = NotificationState::Active;
+                },
+            },
+            NotificationState::Waiting => {
+                let tcb = self
+                    .queue
+                    .dequeue_head()
+                    .expect("Waiting notification queue must not be empty");
+                if self.queue.is_empty() {
+                    self.state = NotificationState::Idle;
+                }
+
+                (*tcb.as_ptr()).blocking_object = None;
+                (*tcb.as_ptr()).set_mr(Tcb::MR1, badge);
+                let _ = scheduler::wake(tcb);
+            },
+            NotificationState::Active => self.word |= badge,
+        }
+    }
+
+    /// Receive pending signals in `thread` first message register.
+    ///
+    /// Without pending signal, `thread` blocks if `blocking` or receives 0.
+    ///
+    /// # Safety
+    /// `thread` must be valid and not already in a queue.
+    pub unsafe fn wait(&mut self, thread: NonNull<Tcb>, blocking: bool) {
+        let thread_ptr = thread.as_ptr();
+
+        match self.state {
+            NotificationState::Idle | NotificationState::Waiting => {
+                if blocking {
+                    (*thread_ptr).state = ThreadState::BlockedOnNotification;
+                    (*thread_ptr).blocking_object =
+                        Some(NonNull::from(&mut *self).cast());
+                    self.queue.append(thread);
+                    self.state = NotificationState::Waiting;
+                } else {
+                    (*thread_ptr).set_mr(Tcb::MR1, 0);
+                }
+            },
+            NotificationState::Active => {
+                (*thread_ptr).set_mr(Tcb::MR1, self.word);
+                self.word = 0;
+                self.state = NotificationState::Idle;
+            },
+        }
+    }
+
+    /// Remove `thread` from waiting queue.
+    ///
+    /// # Safety
+    /// `thread` must wait on this notification.
+    pub unsafe fn cancel(&mut self, thread: NonNull<Tcb>) {
+        self.queue.remove(thread);
+        if self.queue.is_empty() {
+            self.state = NotificationState::Idle;
+        }
+    }
+
+    /// Bind `tcb`, neither of them being already bound.
+    ///
+    /// # Safety
+    /// `tcb` must be valid and outlive the binding.
+    pub unsafe fn bind(&mut self, tcb: NonNull<Tcb>) -> Result<()> {
+        if self.bound_tcb.is_some() || (*tcb.as_ptr()).notification.is_some() {
+            return Err(SysError::InvalidOperation);
+        }
+
+        self.bound_tcb = Some(tcb);
+        (*tcb.as_ptr()).notification = Some(NonNull::from(&mut *self));
+        Ok(())
+    }
+
+    /// Unbind bound thread, if any.
+    ///
+    /// # Safety
+    /// Bound TCB must be valid.
+    pub unsafe fn unbind(&mut self) {
+        if let Some(tcb) = self.bound_tcb.take() {
+            (*tcb.as_ptr()).notification = None;
+        }
+    }
+}
+
+impl Default for NotificationObj {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub type NotificationCap<'a> = CapRef<'a, NotificationObj>;
+
+impl NotificationCap<'_> {
+    const BADGE_OFFSET: usize = 0;
+    const BADGE_WIDTH: usize = 28;
+
+    /// Create a new notification capability.
+    pub const fn mint(
+        paddr: usize,
+        badge: usize,
+        rights: CapRights,
+    ) -> CapRaw {
+        let arg1 = badge & ((1 << Self::BADGE_WIDTH) - 1);
+
+        let mut capraw = CapRaw::default_with_type(ObjType::Notification);
+        capraw.paddr = paddr;
+        capraw.arg1 = arg1;
+        capraw.rights = rights;
+        capraw
+    }
+
+    pub fn badge(&self) -> usize {
+        let raw = self.raw.get();
+        (raw.arg1 >> Self::BADGE_OFFSET) & ((1 << Self::BADGE_WIDTH) - 1)
+    }
+
+    #[inline]
+    pub fn can_send(&self) -> bool {
+        self.rights().contains(CapRights::SEND)
+    }
+
+    #[inline]
+    pub fn can_receive(&self) -> bool {
+        self.rights().contains(CapRights::RECEIVE)
+    }
+
+    /// Get a mutable reference to the notification object.
+    ///
+    /// # Safety
+    /// Caller must ensure exclusive access.
+    pub unsafe fn as_object_mut(&self) -> &'static mut NotificationObj {
+        &mut *phys_to_virt(self.paddr()).as_mut_ptr::<NotificationObj>()
+    }
+
+    pub fn identify(&self, tcb: &mut Tcb) -> usize {
+        tcb.set_mr(Tcb::MR1, self.cap_type() as usize);
+        tcb.set_mr(Tcb::MR2, self.paddr().as_u64() as usize);
+        tcb.set_mr(Tcb::MR3, self.badge());
+        tcb.set_mr(Tcb::MR4, self.rights().bits() as usize);
+        4
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::running;
+
+    #[test_case]
+    fn signals_accumulate_until_wait() {
+        let mut ntfn = NotificationObj::new();
+        let mut tcb = running();
+        let ptr = NonNull::from(&mut tcb);
+
+        unsafe {
+            ntfn.wait(ptr, false);
+            assert_eq!(ptr.as_ref().get_mr(Tcb::MR1), 0);
+
+            ntfn.signal(0b01);
+            ntfn.signal(0b10);
+            assert_eq!(ntfn.state(), NotificationState::Active);
+
+            ntfn.wait(ptr, true);
+            assert_eq!(ptr.as_ref().get_mr(Tcb::MR1), 0b11);
+            assert_eq!(ptr.as_ref().state, ThreadState::Running);
+        }
+        assert_eq!(ntfn.state(), NotificationState::Idle);
+    }
+
+    #[test_case]
+    fn wait_blocks_without_signal() {
+        let mut ntfn = NotificationObj::new();
+        let mut tcb = running();
+        let ptr = NonNull::from(&mut tcb);
+
+        unsafe {
+            ntfn.wait(ptr, true);
+            assert_eq!(ptr.as_ref().state, ThreadState::BlockedOnNotification);
+            assert_eq!(ntfn.state(), NotificationState::Waiting);
+
+            ntfn.cancel(ptr);
+        }
+        assert_eq!(ntfn.state(), NotificationState::Idle);
+    }
+
+    #[test_case]
+    fn bind_refuses_bound() {
+        let mut ntfn = NotificationObj::new();
+        let mut other = NotificationObj::new();
+        let mut tcb = running();
+        let mut second = running();
+        let ptr = NonNull::from(&mut tcb);
+
+        unsafe {
+            ntfn.bind(ptr).unwrap();
+            assert_eq!(
+                ntfn.bind(NonNull::from(&mut second)),
+                Err(SysError::InvalidOperation)
+            );
+            assert_eq!(other.bind(ptr), Err(SysError::InvalidOperation));
+            assert_eq!(ntfn.bound_tcb(), Some(ptr));
+
+            ntfn.unbind();
+            other.bind(ptr).unwrap();
+            other.unbind();
+        }
+    }
+}
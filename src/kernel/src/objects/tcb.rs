@@ -4,9 +4,16 @@ use core::ptr::NonNull;
 
 use crate::arch::trapframe::TrapFrame;
 use crate::cspace::CSpace;
-use crate::error::Result;
-use crate::objects::cnode::CNodeEntry;
-use crate::objects::{CapRaw, CapRef, ObjType};
+use crate::error::{Result, SysError};
+use crate::objects::cnode::{CNodeCap, CNodeEntry};
+use crate::objects::endpoint::EndpointCap;
+use crate::objects::frame::{FrameCap, FrameSize};
+use crate::objects::message::{IpcBuffer, MSG_REGISTERS};
+use crate::objects::notification::NotificationObj;
+use crate::objects::vspace::VSpaceCap;
+use crate::objects::{CapRaw, CapRef, CapRights, ObjType};
+use crate::scheduler::MAX_CPUS;
+use crate::vspace::phys_to_virt;
 
 // Forward declaration for Endpoint to avoid circular dependency.
 pub struct EndpointPtr(pub *mut u8);
@@ -39,6 +46,20 @@ pub enum ThreadState {
     Idle,
 }
 
+impl ThreadState {
+    /// Whether the thread waits on an IPC object.
+    pub const fn is_blocked(self) -> bool {
+        matches!(
+            self,
+            Self::BlockedOnReceive |
+                Self::BlockedOnSend |
+                Self::BlockedOnReply |
+                Self::BlockedOnNotification
+        )
+    }
+}
+
+/// Reason a thread stopped, reported to its fault endpoint.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Fault {
     Cap {
@@ -63,6 +84,60 @@ pub enum Fault {
     Unknown {
         fault_type_raw: usize,
     },
+    /// Page fault in user mode, resolved by a pager.
+    VmFault {
+        /// Faulting address.
+        addr: usize,
+        /// Page fault error code.
+        fsr: usize,
+        instruction_fault: bool,
+    },
+}
+
+impl Fault {
+    /// Label of fault messages, see [`MessageInfo`].
+    ///
+    /// [`MessageInfo`]: crate::objects::message::MessageInfo
+    pub const fn label(&self) -> usize {
+        match self {
+            Self::Cap { .. } => 1,
+            Self::UnknownSyscall { .. } => 2,
+            Self::UserException { .. } => 3,
+            Self::DebugException { .. } => 4,
+            Self::Timeout { .. } => 5,
+            Self::Unknown { .. } => 6,
+            Self::VmFault { .. } => 7,
+        }
+    }
+
+    /// Message words describing this fault, raised in `context`.
+    pub fn message(&self, context: &TrapFrame) -> [usize; MSG_REGISTERS] {
+        let (rip, rsp) = (context.rip, context.rsp);
+        match *self {
+            Self::Cap {
+                address,
+                in_receive_phase,
+            } => [rip, address, in_receive_phase as usize, 0],
+            Self::UnknownSyscall { syscall_number } => {
+                [rip, rsp, syscall_number, 0]
+            },
+            Self::UserException { number, code } => [rip, rsp, number, code],
+            Self::DebugException {
+                exception_reason,
+                breakpoint_address,
+                breakpoint_number,
+            } => {
+                [rip, exception_reason, breakpoint_address, breakpoint_number]
+            },
+            Self::Timeout { badge } => [badge, 0, 0, 0],
+            Self::Unknown { fault_type_raw } => [rip, fault_type_raw, 0, 0],
+            Self::VmFault {
+                addr,
+                fsr,
+                instruction_fault,
+            } => [rip, addr, instruction_fault as usize, fsr],
+        }
+    }
 }
 
 /// Thread control block as defined on seL4 kernel.
@@ -76,7 +151,7 @@ pub struct Tcb {
     /// Notification that this TCB is bound to. If this is set, when this TCB
     /// waits on any sync endpoint, it may receive a signal from a
     /// Notification object.
-    notification: usize,
+    pub notification: Option<NonNull<NotificationObj>>,
 
     /// Current fault.
     fault: Option<Fault>,
@@ -86,7 +161,7 @@ pub struct Tcb {
     /// cannot be in the scheduler queues.
     pub sched_context: Option<NonNull<SchedContext>>,
 
-    /// Userland virtual address of thread IPC buffer.
+    /// Frame backing thread IPC buffer.
     pub ipc_buffer: CNodeEntry,
 
     /// Capability-based root space.
@@ -95,6 +170,8 @@ pub struct Tcb {
 
     /// Thread state.
     pub state: ThreadState,
+    /// Core whose partition schedules this thread.
+    pub affinity: usize,
 
     /// Next TCB in endpoint queue.
     pub ep_next: Option<NonNull<Tcb>>,
@@ -127,12 +204,24 @@ pub struct SchedContext {
     thread: u8,
 }
 
+impl SchedContext {
+    /// Create a [`SchedContext`] with an absolute `deadline`.
+    pub const fn new(deadline: u64) -> Self {
+        Self {
+            ticks: 0,
+            ticks_consumed: 0,
+            deadline,
+            thread: 0,
+        }
+    }
+}
+
 impl Tcb {
     /// Create a new [`Tcb`].
     pub const fn new() -> Self {
         Self {
             context: TrapFrame::new(),
-            notification: 0,
+            notification: None,
             sched_context: None,
             ipc_buffer: CNodeEntry::new(),
             cspace_root: CNodeEntry::new(),
@@ -140,6 +229,7 @@ impl Tcb {
             fault: None,
             fault_ep: CNodeEntry::new(),
             state: ThreadState::Running,
+            affinity: 0,
             ep_next: None,
             ep_prev: None,
             blocking_object: None,
@@ -159,6 +249,98 @@ impl Tcb {
         CSpace::new(&self.cspace_root)
     }
 
+    /// Copy `src` in `dst`, deleting previous capability.
+    fn derive(src: &CNodeEntry, dst: &CNodeEntry) {
+        dst.delete();
+
+        let mut raw = src.get();
+        raw.mdb_prev = None;
+        raw.mdb_next = None;
+        dst.set(raw);
+        CNodeEntry::mdb_insert_after(src, dst);
+    }
+
+    /// Set CSpace and VSpace roots, derived from `cspace` and `vspace`.
+    ///
+    /// `vspace` must have an ASID, its copy would not get one later.
+    pub fn set_roots(
+        &mut self,
+        cspace: &CNodeCap,
+        vspace: &VSpaceCap,
+    ) -> Result<()> {
+        if vspace.asid() == 0 {
+            return Err(SysError::UnableToDerive);
+        }
+
+        Self::derive(cspace.raw, &self.cspace_root);
+        Self::derive(vspace.raw, &self.vspace_root);
+        Ok(())
+    }
+
+    /// VSpace root, if any.
+    pub fn vspace(&self) -> Option<VSpaceCap<'_>> {
+        VSpaceCap::try_from(&self.vspace_root).ok()
+    }
+
+    /// Start in user mode at `rip`, on stack `rsp`, once scheduled.
+    pub fn set_user_entry(&mut self, rip: usize, rsp: usize) {
+        self.context = TrapFrame::user(rip, rsp);
+    }
+
+    /// Set IPC buffer, derived from `frame`, a mapped 4 KiB frame.
+    pub fn set_ipc_buffer(&mut self, frame: &FrameCap) -> Result<()> {
+        if frame.size() != FrameSize::Small || !frame.is_mapped() {
+            return Err(SysError::InvalidValue);
+        }
+
+        Self::derive(frame.raw, &self.ipc_buffer);
+        Ok(())
+    }
+
+    /// IPC buffer, if any.
+    ///
+    /// A `receiver` buffer is written by the kernel, it must be writable by
+    /// user too.
+    pub fn ipc_buffer(
+        &self,
+        receiver: bool,
+    ) -> Option<&'static mut IpcBuffer> {
+        let frame = FrameCap::try_from(&self.ipc_buffer).ok()?;
+        if receiver && !frame.rights().contains(CapRights::WRITE) {
+            return None;
+        }
+
+        // SAFETY: the frame is a 4 KiB page holding plain integers.
+        Some(unsafe { &mut *phys_to_virt(frame.paddr()).as_mut_ptr() })
+    }
+
+    /// Fault the thread is stopped on, if any.
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    pub fn set_fault(&mut self, fault: Option<Fault>) {
+        self.fault = fault;
+    }
+
+    /// Set fault handler, derived from `endpoint`.
+    ///
+    /// Faults are sent as calls, `endpoint` must allow a reply.
+    pub fn set_fault_ep(&mut self, endpoint: &EndpointCap) -> Result<()> {
+        let reply = endpoint.can_grant() || endpoint.can_grant_reply();
+        if !endpoint.can_send() || !reply {
+            return Err(SysError::InvalidOperation);
+        }
+
+        Self::derive(endpoint.raw, &self.fault_ep);
+        Ok(())
+    }
+
+    /// Fault handler endpoint, if any.
+    pub fn fault_ep(&self) -> Option<EndpointCap<'_>> {
+        EndpointCap::try_from(&self.fault_ep).ok()
+    }
+
     pub fn get_mr(&self, idx: usize) -> usize {
         self.context.get_mr(idx)
     }
@@ -171,17 +353,26 @@ impl Tcb {
 #[cfg(target_arch = "x86_64")]
 impl Tcb {
     // RDI. Capacity badge.
-    pub const MR1: usize = 0;
-    // RSI. Message info tag.
-    pub const MR2: usize = 1;
+    pub const MR1: usize = 5;
+    // RSI. Message info tag, see `MessageInfo`.
+    pub const MR2: usize = 4;
     // R10.
     pub const MR3: usize = 9;
     // R8.
-    pub const MR4: usize = 10;
+    pub const MR4: usize = 7;
     // R9.
-    pub const MR5: usize = 11;
+    pub const MR5: usize = 8;
     // R15.
-    pub const MR6: usize = 12;
+    pub const MR6: usize = 14;
+    /// Message registers, in order.
+    pub const MRS: [usize; 6] = [
+        Self::MR1,
+        Self::MR2,
+        Self::MR3,
+        Self::MR4,
+        Self::MR5,
+        Self::MR6,
+    ];
 }
 
 pub type TcbCap<'a> = CapRef<'a, Tcb>;
@@ -193,6 +384,25 @@ impl TcbCap<'_> {
         capraw
     }
 
+    /// Get the thread control block.
+    ///
+    /// # Safety
+    /// Caller must ensure exclusive access.
+    pub unsafe fn as_object_mut(&self) -> &'static mut Tcb {
+        &mut *phys_to_virt(self.paddr()).as_mut_ptr::<Tcb>()
+    }
+
+    /// Run the thread on core `cpu`, from the next time it is queued.
+    pub fn set_affinity(&self, cpu: usize) -> Result<()> {
+        if cpu >= MAX_CPUS {
+            return Err(SysError::RangeError);
+        }
+
+        // SAFETY: the field is only read when the thread is queued.
+        unsafe { self.as_object_mut().affinity = cpu };
+        Ok(())
+    }
+
     pub fn identify(&self, tcb: &mut Tcb) -> usize {
         tcb.set_mr(Tcb::MR1, self.cap_type() as usize);
         1
@@ -1,9 +1,12 @@
 //! Type safety.
 
 use crate::objects::ObjType;
+use crate::objects::asid::{AsidControlObj, AsidPoolObj};
 use crate::objects::cnode::CNodeObj;
 use crate::objects::endpoint::EndpointObj;
 use crate::objects::frame::FrameObj;
+use crate::objects::irq::{IrqControlObj, IrqHandlerObj};
+use crate::objects::notification::NotificationObj;
 use crate::objects::nullcap::NullObj;
 use crate::objects::tcb::Tcb;
 use crate::objects::untyped::UntypedObj;
@@ -40,3 +43,23 @@ impl KernelObject for EndpointObj {
 impl KernelObject for Tcb {
     const OBJ_TYPE: ObjType = ObjType::Tcb;
 }
+
+impl KernelObject for NotificationObj {
+    const OBJ_TYPE: ObjType = ObjType::Notification;
+}
+
+impl KernelObject for IrqControlObj {
+    const OBJ_TYPE: ObjType = ObjType::IrqControl;
+}
+
+impl KernelObject for IrqHandlerObj {
+    const OBJ_TYPE: ObjType = ObjType::Interrupt;
+}
+
+impl KernelObject for AsidControlObj {
+    const OBJ_TYPE: ObjType = ObjType::AsidControl;
+}
+
+impl KernelObject for AsidPoolObj {
+    const OBJ_TYPE: ObjType = ObjType::AsidPool;
+}
@@ -1,13 +1,19 @@
 //! Untyped memory objects and retype operations.
 
+use crate::arch::PhysAddr;
+use crate::arch::vspace::copy_kernel_half;
 use crate::error::{Result, SysError};
 use crate::objects::cnode::{CNODE_ENTRY_BIT_SZ, CNodeEntry, CNodeObj};
+use crate::objects::endpoint::{ENDPOINT_BIT_SZ, EndpointCap, EndpointObj};
 use crate::objects::frame::{FrameObj, FrameSize};
+use crate::objects::notification::{
+    NOTIFICATION_BIT_SZ, NotificationCap, NotificationObj,
+};
 use crate::objects::nullcap::NullCap;
-use crate::objects::tcb::Tcb;
+use crate::objects::tcb::{Tcb, TcbCap, ThreadState};
 use crate::objects::vspace::VSpaceCap;
 use crate::objects::{CapRaw, CapRef, CapRights, ObjType};
-use crate::vspace::PAGE_BITS_4K;
+use crate::vspace::{PAGE_BITS_4K, PAGE_SIZE_4K, phys_to_virt};
 use crate::{alignup, mask};
 
 #[derive(Debug)]
@@ -60,6 +66,8 @@ impl CapRef<'_, UntypedObj> {
                 entry_sz + radix
             },
             ObjType::Tcb => 10, // TCBs are 1024-byte aligned.
+            ObjType::Endpoint => ENDPOINT_BIT_SZ,
+            ObjType::Notification => NOTIFICATION_BIT_SZ,
             _ => bit_size,
         }
     }
@@ -82,6 +90,8 @@ impl CapRef<'_, UntypedObj> {
                 }
             },
             ObjType::Tcb => Some(1 << 10),
+            ObjType::Endpoint => Some(1 << ENDPOINT_BIT_SZ),
+            ObjType::Notification => Some(1 << NOTIFICATION_BIT_SZ),
             ObjType::Untyped => {
                 if user_bits >= Self::MIN_BIT_SIZE && user_bits <= 48 {
                     Some(1 << user_bits)
@@ -114,22 +124,10 @@ impl CapRef<'_, UntypedObj> {
         let align_bits = Self::object_alignment(obj_type, bit_size);
         let obj_size = Self::object_size(obj_type, bit_size)
             .ok_or(SysError::InvalidValue)?;
-        let count = slots.len();
-        let tot_size =
-            count.checked_mul(obj_size).ok_or(SysError::InvalidValue)?;
-        let free_offset = alignup!(self.free_offset(), align_bits);
-
-        let required = free_offset
-            .checked_add(tot_size)
-            .ok_or(SysError::InvalidValue)?;
+        let base_paddr = self.reserve(align_bits, obj_size, slots.len())?;
 
-        if self.size() < required {
-            return Err(SysError::OutOfMemory);
-        }
-
-        let base_paddr = self.paddr().as_u64() as usize;
         for (i, slot) in slots.iter().enumerate() {
-            let addr = base_paddr + free_offset + i * obj_size;
+            let addr = base_paddr + i * obj_size;
             let cap = match obj_type {
                 ObjType::Untyped => CapRef::<UntypedObj>::mint(
                     addr,
@@ -144,7 +142,12 @@ impl CapRef<'_, UntypedObj> {
                     // SAFETY: We own this memory region via the untyped
                     // capability.
                     unsafe {
-                        core::ptr::write_bytes(addr as *mut u8, 0, obj_size);
+                        core::ptr::write_bytes(
+                            phys_to_virt(PhysAddr::new(addr as u64))
+                                .as_mut_ptr::<u8>(),
+                            0,
+                            obj_size,
+                        );
                     }
 
                     CapRef::<CNodeObj>::mint(
@@ -169,16 +172,52 @@ impl CapRef<'_, UntypedObj> {
                     // SAFETY: We own this memory region via the untyped
                     // capability.
                     unsafe {
-                        core::ptr::write_bytes(addr as *mut u8, 0, obj_size);
+                        core::ptr::write_bytes(
+                            phys_to_virt(PhysAddr::new(addr as u64))
+                                .as_mut_ptr::<u8>(),
+                            0,
+                            obj_size,
+                        );
+                        copy_kernel_half(PhysAddr::new(addr as u64));
                     }
 
-                    // Allocate a new ASID.
-                    // For now, use a simple counter based on address.
-                    // TODO: Implement proper ASID pool management.
-                    let asid = ((addr >> PAGE_BITS_4K) & 0xFFFF) as u16;
-                    let asid = if asid == 0 { 1 } else { asid }; // ASID 0 is reserved.
+                    // No ASID until an ASID pool assigns one.
+                    VSpaceCap::mint(addr, 0, CapRights::CONTROL)
+                },
+                ObjType::Tcb => {
+                    // SAFETY: We own this memory region via the untyped
+                    // capability.
+                    unsafe {
+                        let tcb = phys_to_virt(PhysAddr::new(addr as u64))
+                            .as_mut_ptr::<Tcb>();
+                        tcb.write(Tcb::new());
+                        (*tcb).state = ThreadState::Inactive;
+                        (*tcb).affinity = crate::arch::cpuid();
+                    }
 
-                    VSpaceCap::mint(addr, asid, CapRights::CONTROL)
+                    TcbCap::mint(addr)
+                },
+                ObjType::Endpoint => {
+                    // SAFETY: We own this memory region via the untyped
+                    // capability.
+                    unsafe {
+                        phys_to_virt(PhysAddr::new(addr as u64))
+                            .as_mut_ptr::<EndpointObj>()
+                            .write(EndpointObj::new());
+                    }
+
+                    EndpointCap::mint(addr, 0, CapRights::all())
+                },
+                ObjType::Notification => {
+                    // SAFETY: We own this memory region via the untyped
+                    // capability.
+                    unsafe {
+                        phys_to_virt(PhysAddr::new(addr as u64))
+                            .as_mut_ptr::<NotificationObj>()
+                            .write(NotificationObj::new());
+                    }
+
+                    NotificationCap::mint(addr, 0, CapRights::all())
                 },
                 _ => return Err(SysError::InvalidValue),
             };
@@ -186,10 +225,53 @@ impl CapRef<'_, UntypedObj> {
             slot.set(cap);
         }
 
-        self.set_free_offset(free_offset + tot_size);
         Ok(())
     }
 
+    /// Reserve `count` objects of `obj_size` bytes aligned on `align_bits`,
+    /// returning the address of the first one.
+    fn reserve(
+        &self,
+        align_bits: usize,
+        obj_size: usize,
+        count: usize,
+    ) -> Result<usize> {
+        let tot_size =
+            count.checked_mul(obj_size).ok_or(SysError::InvalidValue)?;
+        let free_offset = alignup!(self.free_offset(), align_bits);
+
+        let required = free_offset
+            .checked_add(tot_size)
+            .ok_or(SysError::InvalidValue)?;
+
+        if self.size() < required {
+            return Err(SysError::OutOfMemory);
+        }
+
+        self.set_free_offset(required);
+        Ok(self.paddr().as_u64() as usize + free_offset)
+    }
+
+    /// Carve a zeroed page for a kernel object user code cannot retype,
+    /// such as an ASID pool.
+    pub fn alloc_page(&self) -> Result<PhysAddr> {
+        if self.is_device() {
+            return Err(SysError::InvalidValue);
+        }
+
+        let paddr = self.reserve(PAGE_BITS_4K, PAGE_SIZE_4K, 1)?;
+        let paddr = PhysAddr::new(paddr as u64);
+        // SAFETY: We own this memory region via the untyped capability.
+        unsafe {
+            core::ptr::write_bytes(
+                phys_to_virt(paddr).as_mut_ptr::<u8>(),
+                0,
+                PAGE_SIZE_4K,
+            );
+        }
+        Ok(paddr)
+    }
+
     pub fn identify(&self, tcb: &mut Tcb) -> usize {
         tcb.set_mr(Tcb::MR1, self.cap_type() as usize);
         tcb.set_mr(Tcb::MR2, self.paddr().as_u64() as usize);
@@ -199,3 +281,84 @@ impl CapRef<'_, UntypedObj> {
         5
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::frame::FrameCap;
+
+    const BASE: usize = 0x10_0000;
+    const BITS: usize = 16;
+
+    fn untyped(is_device: bool) -> CNodeEntry {
+        let entry = CNodeEntry::new();
+        entry.set(CapRef::<UntypedObj>::mint(BASE, BITS, is_device));
+        entry
+    }
+
+    #[test_case]
+    fn retype_frames() {
+        let entry = untyped(false);
+        let ut = CapRef::<UntypedObj>::try_from(&entry).unwrap();
+        let slots = [const { CNodeEntry::new() }; 4];
+
+        ut.retype(ObjType::Frame, PAGE_BITS_4K, &slots).unwrap();
+
+        for (i, slot) in slots.iter().enumerate() {
+            let cap = slot.get();
+            assert_eq!(cap.cap_type, ObjType::Frame);
+            assert_eq!(cap.paddr, BASE + (i << PAGE_BITS_4K));
+        }
+        assert_eq!(ut.free_offset(), 4 << PAGE_BITS_4K);
+    }
+
+    #[test_case]
+    fn retype_aligns_free_offset() {
+        let entry = untyped(false);
+        let ut = CapRef::<UntypedObj>::try_from(&entry).unwrap();
+        let frame = [const { CNodeEntry::new() }; 1];
+        let child = [const { CNodeEntry::new() }; 1];
+
+        ut.retype(ObjType::Frame, PAGE_BITS_4K, &frame).unwrap();
+        ut.retype(ObjType::Untyped, 14, &child).unwrap();
+
+        assert_eq!(child[0].get().paddr, BASE + (1 << 14));
+        assert_eq!(ut.free_offset(), 2 << 14);
+    }
+
+    #[test_case]
+    fn retype_out_of_memory() {
+        let entry = untyped(false);
+        let ut = CapRef::<UntypedObj>::try_from(&entry).unwrap();
+        let slots = [const { CNodeEntry::new() }; 17];
+
+        let err = ut.retype(ObjType::Frame, PAGE_BITS_4K, &slots);
+        assert_eq!(err, Err(SysError::OutOfMemory));
+        assert_eq!(ut.free_offset(), 0);
+    }
+
+    #[test_case]
+    fn retype_into_used_slot() {
+        let entry = untyped(false);
+        let ut = CapRef::<UntypedObj>::try_from(&entry).unwrap();
+        let slots = [const { CNodeEntry::new() }; 2];
+        slots[1].set(CapRef::<UntypedObj>::mint(0, BITS, false));
+
+        let err = ut.retype(ObjType::Frame, PAGE_BITS_4K, &slots);
+        assert_eq!(err, Err(SysError::SlotNotEmpty));
+    }
+
+    #[test_case]
+    fn retype_device_only_frames() {
+        let entry = untyped(true);
+        let ut = CapRef::<UntypedObj>::try_from(&entry).unwrap();
+        let slots = [const { CNodeEntry::new() }; 1];
+
+        let err = ut.retype(ObjType::CNode, 10, &slots);
+        assert_eq!(err, Err(SysError::InvalidValue));
+
+        ut.retype(ObjType::Frame, PAGE_BITS_4K, &slots).unwrap();
+        let frame = FrameCap::try_from(&slots[0]).unwrap();
+        assert!(frame.is_device());
+    }
+}
@@ -1,5 +1,6 @@
 //! Virtual address space capabilities.
 
+use crate::arch::ipi::shootdown;
 use crate::arch::vspace::tlb::flush_page;
 #[cfg(target_arch = "x86_64")]
 use crate::arch::vspace::{
@@ -9,6 +10,7 @@ use crate::arch::vspace::{
 use crate::arch::{PhysAddr, VirtAddr};
 use crate::error::{VSpaceError, WalkResult};
 use crate::mask;
+use crate::objects::asid::{ASID_POOL_BITS, ASID_POOLS};
 use crate::objects::frame::{FrameCap, FrameSize};
 use crate::objects::tcb::Tcb;
 use crate::objects::{CapRaw, CapRef, CapRights, ObjType};
@@ -16,8 +18,8 @@ use crate::vspace::{Table, VMAttributes};
 
 pub type Asid = u16;
 
-/// Maximum number of ASIDs supported.
-pub const ASID_MAX: Asid = 0xFFFF;
+/// Highest ASID an ASID pool assigns.
+pub const ASID_MAX: Asid = ((ASID_POOLS << ASID_POOL_BITS) - 1) as Asid;
 
 #[derive(Debug)]
 pub enum VSpaceObj {}
@@ -55,6 +57,15 @@ impl VSpaceCap<'_> {
         ((raw.arg1 >> Self::ASID_OFFSET) & mask!(Self::ASID_WIDTH)) as Asid
     }
 
+    /// Set the ASID given by an ASID pool.
+    pub fn set_asid(&self, asid: Asid) {
+        let mut raw = self.raw.get();
+        raw.arg1 = (raw.arg1 &
+            !(mask!(Self::ASID_WIDTH) << Self::ASID_OFFSET)) |
+            ((asid as usize) << Self::ASID_OFFSET);
+        self.raw.set(raw);
+    }
+
     #[inline]
     pub fn is_active(&self) -> bool {
         let raw = self.raw.get();
@@ -102,6 +113,13 @@ impl VSpaceCap<'_> {
         top_bits == 0 || top_bits == 0x1FFFF
     }
 
+    /// Whether `vaddr` lays in the user half, the kernel half being shared
+    /// by every VSpace.
+    #[inline]
+    pub const fn is_user(vaddr: usize) -> bool {
+        vaddr >> 47 == 0
+    }
+
     #[inline]
     pub unsafe fn pml4<const OFFSET: u64>(&self) -> &'static mut Table<Pml4> {
         Table::<Pml4>::from_paddr::<OFFSET>(self.root_paddr())
@@ -177,7 +195,7 @@ impl VSpaceCap<'_> {
         frame_paddr: PhysAddr,
         attr: VMAttributes,
     ) -> Result<(), VSpaceError> {
-        if !Self::is_canonical(vaddr.as_u64() as usize) {
+        if !Self::is_user(vaddr.as_u64() as usize) {
             return Err(VSpaceError::InvalidVAddr);
         }
 
@@ -242,7 +260,7 @@ impl VSpaceCap<'_> {
         frame_paddr: PhysAddr,
         attr: VMAttributes,
     ) -> Result<(), VSpaceError> {
-        if !Self::is_canonical(vaddr.as_u64() as usize) {
+        if !Self::is_user(vaddr.as_u64() as usize) {
             return Err(VSpaceError::InvalidVAddr);
         }
 
@@ -296,7 +314,7 @@ impl VSpaceCap<'_> {
         frame_paddr: PhysAddr,
         attr: VMAttributes,
     ) -> Result<(), VSpaceError> {
-        if !Self::is_canonical(vaddr.as_u64() as usize) {
+        if !Self::is_user(vaddr.as_u64() as usize) {
             return Err(VSpaceError::InvalidVAddr);
         }
 
@@ -338,6 +356,11 @@ impl VSpaceCap<'_> {
         frame: &FrameCap<'_>,
         user: bool,
     ) -> Result<(), VSpaceError> {
+        // Frames record their mapping by ASID.
+        if self.asid() == 0 {
+            return Err(VSpaceError::InvalidAsid);
+        }
+
         let frame_paddr = frame.paddr();
         let attr = frame.vm_attributes(user);
 
@@ -352,11 +375,12 @@ impl VSpaceCap<'_> {
         }
     }
 
+    /// Unmap `vaddr`, stale TLB entries are invalidated on every core.
     pub unsafe fn unmap<const OFFSET: u64>(
         &self,
         vaddr: VirtAddr,
     ) -> Result<(PhysAddr, FrameSize), VSpaceError> {
-        if !Self::is_canonical(vaddr.as_u64() as usize) {
+        if !Self::is_user(vaddr.as_u64() as usize) {
             return Err(VSpaceError::InvalidVAddr);
         }
 
@@ -381,7 +405,7 @@ impl VSpaceCap<'_> {
         if pdpte.is_page() {
             let paddr = pdpte.paddr();
             *pdpte = Pdpte::invalid();
-            flush_page(vaddr);
+            shootdown(self.asid(), vaddr);
             return Ok((paddr, FrameSize::Huge));
         }
 
@@ -396,7 +420,7 @@ impl VSpaceCap<'_> {
         if pde.is_page() {
             let paddr = pde.paddr();
             *pde = Pde::invalid();
-            flush_page(vaddr);
+            shootdown(self.asid(), vaddr);
             return Ok((paddr, FrameSize::Large));
         }
 
@@ -409,7 +433,7 @@ impl VSpaceCap<'_> {
 
         let paddr = pte.paddr();
         *pte = Pte::invalid();
-        flush_page(vaddr);
+        shootdown(self.asid(), vaddr);
 
         Ok((paddr, FrameSize::Small))
     }
@@ -420,7 +444,7 @@ impl VSpaceCap<'_> {
         level: usize,
         table_paddr: PhysAddr,
     ) -> Result<(), VSpaceError> {
-        if !Self::is_canonical(vaddr.as_u64() as usize) {
+        if !Self::is_user(vaddr.as_u64() as usize) {
             return Err(VSpaceError::InvalidVAddr);
         }
 
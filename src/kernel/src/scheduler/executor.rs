@@ -7,9 +7,10 @@ use heapless::binary_heap::Min;
 
 use crate::arch;
 use crate::objects::tcb::{Tcb, ThreadState};
+use crate::scheduler::lock;
 
 /// Maximum amount of TCB entry on a scheduler.
-const MAX_TCB_PER_CORE: usize = 64;
+pub(super) const MAX_TCB_PER_CORE: usize = 64;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 struct Entry {
@@ -22,6 +23,8 @@ pub enum SchedError {
     QueueFull,
     InvalidState,
     NotFound,
+    /// Target core does not schedule threads.
+    Offline,
 }
 
 #[derive(Default)]
@@ -100,7 +103,12 @@ impl Executor {
         let tcb = next.tcb.as_ptr();
         (*tcb).state = ThreadState::Running;
         self.current = Some(next);
+        arch::percpu::set_current(Some(next.tcb));
 
+        if let Some(vspace) = (*tcb).vspace() {
+            arch::vspace::activate(vspace.root_paddr(), vspace.asid());
+        }
+        lock::release();
         (*tcb).context.restore()
     }
 
@@ -115,10 +123,19 @@ impl Executor {
         }
     }
 
+    /// Thread running on this core.
+    pub fn current(&self) -> Option<NonNull<Tcb>> {
+        self.current.map(|entry| entry.tcb)
+    }
+
     /// Called from timer interrupt.
     pub unsafe fn preempt(&mut self) {
         self.inc_tick();
+        self.reschedule();
+    }
 
+    /// Switch to the head of the ready queue if it has an earlier deadline.
+    pub unsafe fn reschedule(&mut self) {
         if !self.should_preempt() {
             return;
         }
@@ -137,10 +154,15 @@ impl Executor {
         self.schedule()
     }
 
-    unsafe fn block_current(&mut self, state: ThreadState) -> ! {
+    /// Take current thread off this core in `state` and schedule.
+    ///
+    /// [`ThreadState::Inactive`] stops the thread until it is enqueued
+    /// again.
+    pub unsafe fn block_current(&mut self, state: ThreadState) -> ! {
         let valid = matches!(
             state,
-            ThreadState::BlockedOnReceive |
+            ThreadState::Inactive |
+                ThreadState::BlockedOnReceive |
                 ThreadState::BlockedOnSend |
                 ThreadState::BlockedOnReply |
                 ThreadState::BlockedOnNotification
@@ -151,6 +173,7 @@ impl Executor {
         if let Some(cur) = self.current.take() {
             (*cur.tcb.as_ptr()).state = state;
         }
+        arch::percpu::set_current(None);
 
         self.schedule()
     }
@@ -160,15 +183,7 @@ impl Executor {
         &mut self,
         tcb: NonNull<Tcb>,
     ) -> Result<(), SchedError> {
-        let tcb_ref = tcb.as_ref();
-
-        if !matches!(
-            tcb_ref.state,
-            ThreadState::BlockedOnReceive |
-                ThreadState::BlockedOnSend |
-                ThreadState::BlockedOnReply |
-                ThreadState::BlockedOnNotification
-        ) {
+        if !tcb.as_ref().state.is_blocked() {
             return Err(SchedError::InvalidState);
         }
 
@@ -189,6 +204,7 @@ impl Executor {
             self.context_switch(entry);
         }
 
+        lock::release();
         loop {
             arch::halt();
         }
@@ -198,3 +214,113 @@ impl Executor {
         unsafe { self.schedule() }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::VirtAddr;
+    use crate::arch::vspace::kernel_paddr;
+    use crate::error::SysError;
+    use crate::objects::cnode::CNodeEntry;
+    use crate::objects::tcb::{SchedContext, TcbCap};
+    use crate::scheduler::{MAX_CPUS, enqueue, remote};
+
+    /// Thread never crossing a page.
+    #[repr(C, align(1024))]
+    struct Thread(Tcb);
+
+    fn tcb(sched: &mut SchedContext) -> Tcb {
+        let mut tcb = Tcb::new();
+        tcb.state = ThreadState::Inactive;
+        tcb.sched_context = Some(NonNull::from(sched));
+        tcb
+    }
+
+    #[test_case]
+    fn enqueue_requires_inactive() {
+        let mut executor = Executor::new();
+        let mut tcb = Tcb::new();
+        tcb.state = ThreadState::Running;
+
+        let res = unsafe { executor.enqueue(NonNull::from(&mut tcb)) };
+        assert_eq!(res, Err(SchedError::InvalidState));
+    }
+
+    #[test_case]
+    fn ready_queue_is_edf() {
+        let mut executor = Executor::new();
+        let mut scs = [30, 10, 20].map(SchedContext::new);
+        let [a, b, c] = &mut scs;
+        let mut tcbs = [tcb(a), tcb(b), tcb(c)];
+
+        for t in &mut tcbs {
+            unsafe { executor.enqueue(NonNull::from(t)).unwrap() };
+        }
+
+        for deadline in [10, 20, 30] {
+            assert_eq!(executor.ready.pop().unwrap().deadline, deadline);
+        }
+        assert!(executor.ready.is_empty());
+    }
+
+    #[test_case]
+    fn preempt_on_earlier_deadline() {
+        let mut executor = Executor::new();
+        let mut late = SchedContext::new(100);
+        let mut early = SchedContext::new(5);
+        let mut current = tcb(&mut late);
+        let mut next = tcb(&mut early);
+
+        executor.current = Some(Entry {
+            deadline: 100,
+            tcb: NonNull::from(&mut current),
+        });
+        assert!(!executor.should_preempt());
+
+        unsafe { executor.enqueue(NonNull::from(&mut next)).unwrap() };
+        assert!(executor.should_preempt());
+    }
+
+    #[test_case]
+    fn wake_blocked_thread() {
+        let mut executor = Executor::new();
+        let mut sc = SchedContext::new(1);
+        let mut blocked = tcb(&mut sc);
+        let ptr = NonNull::from(&mut blocked);
+
+        let res = unsafe { executor.wake(ptr) };
+        assert_eq!(res, Err(SchedError::InvalidState));
+
+        unsafe {
+            (*ptr.as_ptr()).state = ThreadState::BlockedOnReceive;
+            executor.wake(ptr).unwrap();
+            assert_eq!(ptr.as_ref().state, ThreadState::Inactive);
+        }
+        assert_eq!(executor.ready.len(), 1);
+    }
+
+    #[test_case]
+    fn enqueue_on_affinity_core() {
+        // No core runs with this index in tests.
+        let cpu = MAX_CPUS - 1;
+        let mut thread = Thread(Tcb::new());
+        thread.0.state = ThreadState::Inactive;
+        let paddr = kernel_paddr(VirtAddr::from_ptr(&thread)).unwrap();
+        let entry = CNodeEntry::new();
+        entry.set(TcbCap::mint(paddr.as_u64() as usize));
+        let cap = TcbCap::try_from(&entry).unwrap();
+
+        assert_eq!(cap.set_affinity(MAX_CPUS), Err(SysError::RangeError));
+        cap.set_affinity(cpu).unwrap();
+        assert_ne!(thread.0.affinity, arch::cpuid());
+
+        let mut executor = Executor::new();
+        let ptr = NonNull::from(&mut thread.0);
+        unsafe {
+            assert_eq!(enqueue(ptr), Err(SchedError::Offline));
+            remote::push(ptr.as_ref().affinity, ptr).unwrap();
+            remote::drain(cpu, &mut executor);
+        }
+        assert_eq!(executor.ready.peek().map(|entry| entry.tcb), Some(ptr));
+    }
+}
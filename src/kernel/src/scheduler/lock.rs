@@ -0,0 +1,59 @@
+//! Kernel lock.
+//!
+//! Endpoint and notification queues, threads and CNode slots are reached
+//! from every core. Syscalls and interrupts hold the kernel lock from their
+//! entry until they return, or until their core switches to a thread.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::arch::{self, ipi};
+
+/// Owner of a free lock.
+const FREE: usize = usize::MAX;
+
+/// Core holding the lock, [`FREE`] if none.
+static OWNER: AtomicUsize = AtomicUsize::new(FREE);
+
+/// Kernel lock held by current core, released when dropped.
+#[derive(Debug)]
+pub struct KernelLock(());
+
+impl Drop for KernelLock {
+    fn drop(&mut self) {
+        release();
+    }
+}
+
+/// Take the kernel lock.
+///
+/// Waiting cores serve TLB shootdowns, since the owner may wait on them.
+pub fn acquire() -> KernelLock {
+    let cpu = arch::cpuid();
+    debug_assert_ne!(
+        OWNER.load(Ordering::Relaxed),
+        cpu,
+        "kernel lock taken twice"
+    );
+
+    while OWNER
+        .compare_exchange_weak(FREE, cpu, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        ipi::handle_shootdown();
+        core::hint::spin_loop();
+    }
+    KernelLock(())
+}
+
+/// Release the kernel lock, if current core holds it.
+///
+/// Called before switching to a thread, the entry which took the lock
+/// never returns.
+pub fn release() {
+    let _ = OWNER.compare_exchange(
+        arch::cpuid(),
+        FREE,
+        Ordering::Release,
+        Ordering::Relaxed,
+    );
+}
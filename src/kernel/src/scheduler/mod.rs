@@ -1,22 +1,117 @@
 //! EDF-like scheduler.
+//!
+//! Threads run on their affinity core, set with [`TcbCap::set_affinity`].
+//! Waking up a thread of another core hands it over through that core
+//! inbox, then a wake IPI. Threads of any core talk through the same
+//! endpoints and notifications, under the kernel [`lock`].
+//!
+//! [`TcbCap::set_affinity`]: crate::objects::tcb::TcbCap::set_affinity
+
+use core::ptr::NonNull;
 
 /// Scheduler.
 pub mod executor;
 
+/// Kernel lock, shared by every core.
+pub mod lock;
+
 /// Unsafe percore manipulation.
 mod percore;
 
+/// Threads handed over between cores.
+mod remote;
+
 /// `OnceLock`-like.
 mod sync;
 
+use crate::arch;
+use crate::arch::ipi::{self, Ipi};
+use crate::arch::percpu;
+use crate::objects::tcb::{Tcb, ThreadState};
+use crate::scheduler::executor::{Executor, SchedError};
+pub use crate::scheduler::percore::MAX_CPUS;
 use crate::scheduler::percore::PerCore;
 use crate::scheduler::sync::OnceLock;
 
 pub static SCHEDULER: OnceLock<PerCore<executor::Executor>> = OnceLock::new();
 
-/// Inits per-core scheduler.
+/// Inits per-core scheduler and attaches the boot core to its executor.
 pub fn init_scheduler() {
-    let cores = crate::arch::sysinfo().cores as usize;
+    let cores = crate::arch::sysinfo().threads as usize;
     let _ = SCHEDULER.set(PerCore::new(cores));
+    init_core();
     log::info!("{cores} schedulers initialized");
 }
+
+/// Attach current core to its executor.
+pub fn init_core() {
+    let executor = SCHEDULER
+        .get()
+        .and_then(PerCore::current)
+        .expect("core has no executor");
+    percpu::set_executor(executor);
+}
+
+/// Current core executor.
+///
+/// # Safety
+/// Interrupts must be disabled and no other reference to the executor may
+/// be live, which holds from entry to exit of the kernel.
+pub unsafe fn executor() -> &'static mut Executor {
+    let executor = percpu::executor().expect("scheduler not initialized");
+    &mut *executor.as_ptr()
+}
+
+/// Queue [`ThreadState::Inactive`] `tcb` on its affinity core.
+///
+/// Another core gets it through a wake IPI.
+///
+/// # Safety
+/// `tcb` must be valid.
+pub unsafe fn enqueue(tcb: NonNull<Tcb>) -> Result<(), SchedError> {
+    if percpu::executor().is_none() {
+        return Err(SchedError::Offline);
+    }
+    let cpu = tcb.as_ref().affinity;
+    if cpu == arch::cpuid() {
+        return executor().enqueue(tcb);
+    }
+
+    if tcb.as_ref().state != ThreadState::Inactive {
+        return Err(SchedError::InvalidState);
+    }
+    if !ipi::is_online(cpu) {
+        return Err(SchedError::Offline);
+    }
+
+    remote::push(cpu, tcb)?;
+    ipi::send(cpu, Ipi::Wake);
+    Ok(())
+}
+
+/// Make blocked `tcb` runnable on its affinity core.
+///
+/// # Safety
+/// `tcb` must be valid.
+pub unsafe fn wake(tcb: NonNull<Tcb>) -> Result<(), SchedError> {
+    if percpu::executor().is_none() {
+        return Err(SchedError::Offline);
+    }
+    if !tcb.as_ref().state.is_blocked() {
+        return Err(SchedError::InvalidState);
+    }
+
+    (*tcb.as_ptr()).state = ThreadState::Inactive;
+    enqueue(tcb)
+}
+
+/// Queue threads handed over by other cores, then switch to the earliest
+/// deadline.
+///
+/// # Safety
+/// Must be called from the wake IPI handler.
+pub unsafe fn wake_remote() {
+    let executor = executor();
+    remote::drain(arch::cpuid(), executor);
+    executor.reschedule();
+}
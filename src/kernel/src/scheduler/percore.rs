@@ -1,13 +1,19 @@
+use core::cell::UnsafeCell;
 use core::mem::MaybeUninit;
+use core::ptr::NonNull;
 use core::sync::atomic::{AtomicUsize, Ordering};
 
 pub const MAX_CPUS: usize = 16;
 
 use crate::arch::cpuid;
 
+/// One `T` per core, indexed by logical core index.
+///
+/// Values are only reached through pointers: each core mutates its own one,
+/// see [`crate::scheduler::executor()`].
 #[derive(Debug)]
 pub struct PerCore<T> {
-    cores: [MaybeUninit<T>; MAX_CPUS],
+    cores: [UnsafeCell<MaybeUninit<T>>; MAX_CPUS],
     initialized_count: AtomicUsize,
 }
 
@@ -25,8 +31,7 @@ impl<T> PerCore<T> {
         }
 
         unsafe {
-            let ptr = self.cores[cpu_id].as_ptr() as *mut T;
-            ptr.write(init());
+            (*self.cores[cpu_id].get()).write(init());
         }
         self.initialized_count.fetch_add(1, Ordering::Release);
     }
@@ -37,7 +42,8 @@ impl<T> PerCore<T> {
         T: Default,
     {
         let percore = Self {
-            cores: [const { MaybeUninit::uninit() }; MAX_CPUS],
+            cores: [const { UnsafeCell::new(MaybeUninit::uninit()) };
+                MAX_CPUS],
             initialized_count: AtomicUsize::new(0),
         };
         for i in 0..n {
@@ -46,21 +52,16 @@ impl<T> PerCore<T> {
         percore
     }
 
-    /// Unsafe access to a core scheduler.
-    pub unsafe fn get_unsafe(&self, i: usize) -> &T {
-        unsafe { self.cores[i].assume_init_ref() }
-    }
-
-    /// Access to current core scheduler.
-    pub fn get(&self) -> &T {
-        let i = cpuid().try_into().unwrap_or(0);
-        unsafe { self.cores[i].assume_init_ref() }
+    /// Value of core `i`, if it is initialized.
+    pub fn get(&self, i: usize) -> Option<NonNull<T>> {
+        if i >= self.initialized_count.load(Ordering::Acquire) {
+            return None;
+        }
+        NonNull::new(self.cores[i].get().cast())
     }
 
-    /// Mutable access to current core scheduler.
-    #[allow(clippy::mut_from_ref)]
-    pub fn get_mut(&self) -> &mut T {
-        let i = cpuid().try_into().unwrap_or(0);
-        unsafe { &mut *(self.cores[i].as_ptr() as *mut T) }
+    /// Value of current core.
+    pub fn current(&self) -> Option<NonNull<T>> {
+        self.get(cpuid())
     }
 }
@@ -0,0 +1,62 @@
+use core::ptr::NonNull;
+
+use heapless::Deque;
+use spin::Mutex;
+
+use crate::objects::tcb::Tcb;
+use crate::scheduler::MAX_CPUS;
+use crate::scheduler::executor::{Executor, MAX_TCB_PER_CORE, SchedError};
+
+/// Threads queued by other cores, waiting for their core to pick them.
+struct Inbox(Deque<NonNull<Tcb>, MAX_TCB_PER_CORE>);
+
+// SAFETY: TCBs are only handed over, the target core enqueues them.
+unsafe impl Send for Inbox {}
+
+static INBOXES: [Mutex<Inbox>; MAX_CPUS] =
+    [const { Mutex::new(Inbox(Deque::new())) }; MAX_CPUS];
+
+/// Hand inactive `tcb` over to `cpu`.
+pub fn push(cpu: usize, tcb: NonNull<Tcb>) -> Result<(), SchedError> {
+    let inbox = INBOXES.get(cpu).ok_or(SchedError::Offline)?;
+    inbox
+        .lock()
+        .0
+        .push_back(tcb)
+        .map_err(|_| SchedError::QueueFull)
+}
+
+/// Enqueue threads handed over to `cpu` in its `executor`.
+///
+/// # Safety
+/// Handed over TCBs must be valid.
+pub unsafe fn drain(cpu: usize, executor: &mut Executor) {
+    let mut inbox = INBOXES[cpu].lock();
+    while let Some(tcb) = inbox.0.pop_front() {
+        if executor.enqueue(tcb).is_err() {
+            log::warn!("core {cpu} dropped a woken up thread");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::tcb::ThreadState;
+
+    #[test_case]
+    fn drain_empties_inbox() {
+        // No core runs with this index in tests.
+        let cpu = MAX_CPUS - 1;
+        let mut executor = Executor::new();
+        let mut tcb = Tcb::new();
+        tcb.state = ThreadState::Inactive;
+        let ptr = NonNull::from(&mut tcb);
+
+        push(cpu, ptr).unwrap();
+        assert_eq!(push(MAX_CPUS, ptr), Err(SchedError::Offline));
+
+        unsafe { drain(cpu, &mut executor) };
+        assert!(INBOXES[cpu].lock().0.is_empty());
+    }
+}
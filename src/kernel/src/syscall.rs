@@ -1,9 +1,22 @@
 //! Syscalls definition.
 
-use core::fmt;
+use core::ptr::NonNull;
 
 use num_enum::{FromPrimitive, IntoPrimitive};
 
+use crate::error::{Result, SysError};
+use crate::objects::CapRef;
+use crate::objects::asid::{AsidControlCap, AsidPoolCap};
+use crate::objects::endpoint::{
+    EndpointCap, receive_ipc, reply_ipc, send_ipc,
+};
+use crate::objects::irq::{IrqControlCap, IrqHandlerCap};
+use crate::objects::notification::NotificationCap;
+use crate::objects::tcb::{Tcb, TcbCap};
+use crate::objects::traits::KernelObject;
+use crate::objects::untyped::UntypedObj;
+use crate::objects::vspace::VSpaceCap;
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, FromPrimitive, IntoPrimitive)]
 #[repr(u8)]
 pub enum Syscall {
@@ -11,12 +24,22 @@ pub enum Syscall {
     CreateTask = 1,
     RemoveTask = 2,
     TaskSleep = 3,
+    AckIrq = 4,
+    SetFaultHandler = 5,
+    MakeAsidPool = 6,
+    AssignAsid = 7,
+    SetAffinity = 8,
     MapMemory = 10,
     UnmapMemory = 11,
     GrantMemory = 12,
     Send = 20,
     Receive = 21,
     IpcCall = 22,
+    Reply = 23,
+    Signal = 24,
+    Wait = 25,
+    Poll = 26,
+    BindNotification = 27,
     #[num_enum(catch_all)]
     Invalid(u8) = 255,
 }
@@ -29,30 +52,68 @@ impl From<u64> for Syscall {
     }
 }
 
-#[derive(Debug)]
-pub enum SysError {
-    InvalidValue,
-    UnknownSyscall(u8),
-}
-
-impl fmt::Display for SysError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{self:?}")
-    }
+/// Look up capability `cptr` to a `T` in `tcb` CSpace.
+fn lookup<T: KernelObject>(tcb: &Tcb, cptr: u64) -> Result<CapRef<'_, T>> {
+    let slot = tcb.cspace()?.lookup(cptr as usize)?;
+    CapRef::try_from(slot)
 }
 
-impl core::error::Error for SysError {}
-
-/// Handle inbound syscall.
+/// Handle inbound syscall from `current` thread.
+///
+/// Blocking calls only update `current` state, switching to another thread
+/// is left to the caller. Invocations returning data write it in `current`
+/// message registers, the caller writes the error code in RAX.
 #[inline]
 pub fn handler<I: Into<Syscall>>(
     id: I,
-    _args: [u64; 6],
-) -> Result<(), SysError> {
+    args: [u64; 6],
+    current: NonNull<Tcb>,
+) -> Result<()> {
     let id = id.into();
+    // SAFETY: current thread is valid while it runs.
+    let caller = unsafe { current.as_ref() };
 
     match id {
-        Syscall::AttachIrq => unimplemented!(),
+        Syscall::AttachIrq => {
+            let control: IrqControlCap = lookup(caller, args[0])?;
+            let ntfn: NotificationCap = lookup(caller, args[3])?;
+            let slot = caller.cspace()?.lookup(args[2] as usize)?;
+            if !ntfn.can_send() {
+                return Err(SysError::InvalidOperation);
+            }
+
+            control.issue(args[1] as usize, slot)?;
+            IrqHandlerCap::try_from(slot)?.set_notification(&ntfn)?;
+        },
+        Syscall::AckIrq => {
+            let handler: IrqHandlerCap = lookup(caller, args[0])?;
+            handler.ack();
+        },
+        Syscall::SetFaultHandler => {
+            let tcb: TcbCap = lookup(caller, args[0])?;
+            let ep: EndpointCap = lookup(caller, args[1])?;
+
+            // SAFETY: only the fault endpoint slot of the thread is
+            // written, it is read when the thread faults.
+            unsafe { tcb.as_object_mut().set_fault_ep(&ep)? };
+        },
+        Syscall::MakeAsidPool => {
+            let control: AsidControlCap = lookup(caller, args[0])?;
+            let untyped: CapRef<UntypedObj> = lookup(caller, args[1])?;
+            let slot = caller.cspace()?.lookup(args[2] as usize)?;
+
+            control.make_pool(&untyped, slot)?;
+        },
+        Syscall::AssignAsid => {
+            let pool: AsidPoolCap = lookup(caller, args[0])?;
+            let vspace: VSpaceCap = lookup(caller, args[1])?;
+
+            pool.assign(&vspace)?;
+        },
+        Syscall::SetAffinity => {
+            let tcb: TcbCap = lookup(caller, args[0])?;
+            tcb.set_affinity(args[1] as usize)?;
+        },
         Syscall::CreateTask => {
             /*if args.len() < 3 {
                 return Err(SysError::InvalidValue);
@@ -65,8 +126,69 @@ pub fn handler<I: Into<Syscall>>(
             // });
             // SCHEDULER.get().unwrap().get_mut().spawn(task);*/
         },
-        Syscall::Invalid(id) => return Err(SysError::UnknownSyscall(id)),
-        _ => unimplemented!(),
+        Syscall::Send | Syscall::IpcCall => {
+            let ep: EndpointCap = lookup(caller, args[0])?;
+            if !ep.can_send() {
+                return Err(SysError::InvalidOperation);
+            }
+
+            // SAFETY: `current` and threads queued on `ep` are valid.
+            unsafe {
+                send_ipc(
+                    true,
+                    id == Syscall::IpcCall,
+                    ep.badge(),
+                    ep.can_grant(),
+                    ep.can_grant_reply(),
+                    current,
+                    &ep,
+                )?
+            };
+        },
+        Syscall::Receive => {
+            let ep: EndpointCap = lookup(caller, args[0])?;
+            if !ep.can_receive() {
+                return Err(SysError::InvalidOperation);
+            }
+
+            // SAFETY: `current` and threads queued on `ep` are valid.
+            unsafe { receive_ipc(current, &ep, true)? };
+        },
+        // SAFETY: `current` and its caller are valid.
+        Syscall::Reply => unsafe { reply_ipc(current)? },
+        Syscall::Signal => {
+            let ntfn: NotificationCap = lookup(caller, args[0])?;
+            if !ntfn.can_send() {
+                return Err(SysError::InvalidOperation);
+            }
+
+            // SAFETY: the kernel lock is held, see `syscall_entry`.
+            unsafe { ntfn.as_object_mut().signal(ntfn.badge()) };
+        },
+        Syscall::Wait | Syscall::Poll => {
+            let ntfn: NotificationCap = lookup(caller, args[0])?;
+            if !ntfn.can_receive() {
+                return Err(SysError::InvalidOperation);
+            }
+
+            // SAFETY: the kernel lock is held, see `syscall_entry`.
+            unsafe { ntfn.as_object_mut().wait(current, id == Syscall::Wait) };
+        },
+        Syscall::BindNotification => {
+            let ntfn: NotificationCap = lookup(caller, args[0])?;
+            let tcb: TcbCap = lookup(caller, args[1])?;
+            if !ntfn.can_receive() {
+                return Err(SysError::InvalidOperation);
+            }
+
+            // SAFETY: the kernel lock is held, see `syscall_entry`.
+            unsafe {
+                let tcb = NonNull::from(tcb.as_object_mut());
+                ntfn.as_object_mut().bind(tcb)?;
+            }
+        },
+        Syscall::Invalid(_) => return Err(SysError::UnknownSyscall),
+        _ => return Err(SysError::UnsupportedSyscallOp),
     };
 
     Ok(())
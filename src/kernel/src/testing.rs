@@ -0,0 +1,87 @@
+//! In-kernel test framework.
+//!
+//! `cargo test` builds the kernel with every `#[test_case]` function and
+//! boots it headless through the host runner. Results are written on COM1,
+//! which needs the `serial` feature, and reported to the runner through the
+//! `isa-debug-exit` device.
+
+use crate::arch::console::serial;
+use crate::arch::qemu::{QemuExitCode, exit_qemu};
+use crate::arch::vspace::kernel_paddr;
+use crate::arch::{PhysAddr, VirtAddr};
+use crate::objects::cnode::CNodeEntry;
+use crate::objects::tcb::{Tcb, ThreadState};
+use crate::vspace::{PAGE_SIZE_4K, phys_to_virt};
+
+/// Radix of [`Node`].
+pub const NODE_RADIX: usize = 4;
+
+/// Kernel test case.
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        serial_print!("{}...\t", core::any::type_name::<T>());
+        self();
+        serial_println!("[ok]");
+    }
+}
+
+/// Run every test case then exit QEMU.
+pub fn runner(tests: &[&dyn Testable]) {
+    serial_println!("running {} tests", tests.len());
+    for test in tests {
+        test.run();
+    }
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// Report a failed test case then exit QEMU.
+pub fn panic(info: &core::panic::PanicInfo) -> ! {
+    serial::panic_print(format_args!("[failed]\n{info}\n"));
+    exit_qemu(QemuExitCode::Failed);
+}
+
+/// Zeroed page, for objects reached through the physical memory window.
+#[repr(C, align(4096))]
+pub struct Page([u8; PAGE_SIZE_4K]);
+
+impl Page {
+    pub const fn new() -> Self {
+        Self([0; PAGE_SIZE_4K])
+    }
+
+    pub fn paddr(&self) -> PhysAddr {
+        kernel_paddr(VirtAddr::from_ptr(self)).expect("test page not mapped")
+    }
+}
+
+/// CNode small enough to never cross a page.
+#[repr(C, align(1024))]
+pub struct Node(pub [CNodeEntry; 1 << NODE_RADIX]);
+
+impl Node {
+    pub const fn new() -> Self {
+        Self([const { CNodeEntry::new() }; 1 << NODE_RADIX])
+    }
+
+    pub fn paddr(&self) -> usize {
+        let vaddr = VirtAddr::from_ptr(self);
+        kernel_paddr(vaddr).unwrap().as_u64() as usize
+    }
+
+    /// Slot `idx` as seen through the physical memory window.
+    pub fn slot(&self, idx: usize) -> *const CNodeEntry {
+        let paddr = self.paddr() + idx * size_of::<CNodeEntry>();
+        phys_to_virt(PhysAddr::new(paddr as u64)).as_ptr()
+    }
+}
+
+/// Thread running, as a syscall caller.
+pub fn running() -> Tcb {
+    let mut tcb = Tcb::new();
+    tcb.state = ThreadState::Running;
+    tcb
+}
@@ -15,6 +15,15 @@ pub const PAGE_BITS_1G: usize = 30;
 pub const ENTRIES_PER_TABLE: usize = 512;
 pub const ENTRIES_BITS: usize = 9;
 
+/// Kernel virtual address of `paddr` in the physical memory window.
+///
+/// Capabilities hold physical addresses, the kernel reaches objects memory
+/// through the bootloader mapping at [`crate::PHYS_MEM_OFFSET`].
+#[inline]
+pub fn phys_to_virt(paddr: PhysAddr) -> VirtAddr {
+    VirtAddr::new(paddr.as_u64() + crate::PHYS_MEM_OFFSET)
+}
+
 pub trait Level: Copy + Clone {
     const LEVEL: usize;
 }
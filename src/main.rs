@@ -1,22 +1,137 @@
 //! Modified version of [bootloader example](https://github.com/rust-osdev/bootloader/blob/main/docs/create-disk-image.md).
 
-fn main() {
-    // read env variables that were set in build script
-    let uefi_path = env!("UEFI_PATH");
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, ExitCode, Stdio};
+use std::time::{Duration, Instant};
+use std::{fs, thread};
+
+/// I/O base of the `isa-debug-exit` device, must match the kernel.
+const DEBUG_EXIT_IOBASE: u16 = 0xf4;
+/// QEMU status when the kernel writes `0x10` (`(0x10 << 1) | 1`).
+const QEMU_SUCCESS: i32 = 0x21;
+/// QEMU status when the kernel writes `0x11` (`(0x11 << 1) | 1`).
+const QEMU_FAILURE: i32 = 0x23;
+/// Test kernels running longer than this are killed.
+const TEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// Process status reported when a test kernel timed out.
+const TIMEOUT_STATUS: u8 = 124;
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+
+    match args.next().as_deref() {
+        None | Some("run") => run(),
+        Some("test") => match args.next() {
+            Some(kernel) => test(Path::new(&kernel)),
+            None => {
+                eprintln!("usage: chick test <kernel>");
+                ExitCode::from(2)
+            },
+        },
+        Some(mode) => {
+            eprintln!("unknown mode `{mode}`, expected `run` or `test`");
+            ExitCode::from(2)
+        },
+    }
+}
 
-    let mut cmd = std::process::Command::new("qemu-system-x86_64");
+/// QEMU command shared by every mode.
+fn qemu(uefi_path: &Path) -> Command {
+    let mut cmd = Command::new("qemu-system-x86_64");
     cmd.arg("-bios").arg(ovmf_prebuilt::ovmf_pure_efi());
     cmd.arg("-drive")
-        .arg(format!("format=raw,file={uefi_path}"));
+        .arg(format!("format=raw,file={}", uefi_path.display()));
 
     // Enable serial output.
     cmd.arg("-serial").arg("stdio");
 
     cmd.arg("-m").arg("50M");
     cmd.arg("-smp").arg("cores=4");
+    cmd
+}
+
+/// Boot the kernel packed by the build script interactively.
+fn run() -> ExitCode {
+    // read env variables that were set in build script
+    let uefi_path = Path::new(env!("UEFI_PATH"));
+
+    let mut cmd = qemu(uefi_path);
     cmd.arg("-monitor")
         .arg("telnet:127.0.0.1:7000,server,nowait");
 
     let mut child = cmd.spawn().unwrap();
     child.wait().unwrap();
+    ExitCode::SUCCESS
+}
+
+/// Boot a test kernel headless and map its exit code to a process status.
+///
+/// Used as cargo runner by the kernel crate, `kernel` is the test binary.
+fn test(kernel: &Path) -> ExitCode {
+    let uefi_path = kernel.with_extension("img");
+    bootloader::UefiBoot::new(kernel)
+        .set_ramdisk(Path::new(env!("ROOTTASK_PATH")))
+        .create_disk_image(&uefi_path)
+        .unwrap();
+
+    let mut cmd = qemu(&uefi_path);
+    cmd.arg("-device").arg(format!(
+        "isa-debug-exit,iobase={DEBUG_EXIT_IOBASE:#x},iosize=0x04"
+    ));
+    cmd.arg("-display").arg("none");
+    cmd.stdout(Stdio::piped());
+
+    let mut child = cmd.spawn().unwrap();
+    let stdout = child.stdout.take().unwrap();
+
+    // Forward serial output while capturing it.
+    let serial = thread::spawn(move || {
+        let mut captured = String::new();
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            println!("{line}");
+            captured.push_str(&line);
+            captured.push('\n');
+        }
+        captured
+    });
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break Some(status);
+        }
+        if start.elapsed() > TEST_TIMEOUT {
+            child.kill().unwrap();
+            child.wait().unwrap();
+            break None;
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+
+    let log_path = kernel.with_extension("serial.log");
+    fs::write(&log_path, serial.join().unwrap()).unwrap();
+
+    match status.and_then(|status| status.code()) {
+        Some(QEMU_SUCCESS) => ExitCode::SUCCESS,
+        Some(QEMU_FAILURE) => {
+            eprintln!("tests failed, serial log at {}", log_path.display());
+            ExitCode::FAILURE
+        },
+        Some(code) => {
+            eprintln!(
+                "qemu exited with unexpected status {code}, serial log at {}",
+                log_path.display()
+            );
+            ExitCode::FAILURE
+        },
+        None => {
+            eprintln!(
+                "tests timed out after {}s, serial log at {}",
+                TEST_TIMEOUT.as_secs(),
+                log_path.display()
+            );
+            ExitCode::from(TIMEOUT_STATUS)
+        },
+    }
 }
@@ -0,0 +1,15 @@
+use std::env;
+
+fn main() {
+    // Host builds (e.g. `cargo check --workspace`) use the default layout.
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("none") {
+        return;
+    }
+
+    let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+
+    // The kernel only loads static executables.
+    println!("cargo:rustc-link-arg-bins=--no-pie");
+    println!("cargo:rustc-link-arg-bins=-T{dir}/link.ld");
+    println!("cargo:rerun-if-changed=link.ld");
+}
@@ -0,0 +1,21 @@
+//! Root task, first user thread started by the kernel.
+//!
+//! The kernel loads it from the bootloader ramdisk and hands it every
+//! capability of the root CNode, described by the read-only boot info page
+//! whose address is the first argument of `_start`.
+#![no_std]
+#![no_main]
+
+#[unsafe(no_mangle)]
+extern "C" fn _start(_boot_info: *const u8) -> ! {
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {
+        core::hint::spin_loop();
+    }
+}